@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct GiteaMetadata {
+    pub pusher_id: u64,
+    pub pusher_name: String,
+    pub pusher_email: Option<String>,
+    pub repository_id: u64,
+    pub repository_owner: String,
+    pub repository_name: String,
+    pub pull_request_id: Option<u64>,
+}
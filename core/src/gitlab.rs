@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 #[serde(tag = "type", rename_all = "kebab-case")]
 pub enum GitlabId {
     User { id: u64 },
@@ -35,19 +35,19 @@ impl FromStr for GitlabId {
         if let Some(suffix) = s.strip_prefix("user-") {
             return suffix.parse::<u64>()
                 .map(|id| GitlabId::User{id})
-                .map_err(|e| GitlabParseError::ParseIntError(e))
+                .map_err(GitlabParseError::ParseIntError)
         }
         if let Some(suffix) = s.strip_prefix("key-") {
             return suffix.parse::<u64>()
                 .map(|id| GitlabId::Key{id})
-                .map_err(|e| GitlabParseError::ParseIntError(e))
+                .map_err(GitlabParseError::ParseIntError)
         }
         Err(GitlabParseError::UnsupportedInput(s.to_string()))
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
-#[serde(rename_all = "kebab-case")]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
 pub enum GitlabProtocol {
     HTTP,
     SSH,
@@ -67,7 +67,7 @@ impl FromStr for GitlabProtocol {
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum GitlabRepository {
     #[serde(rename = "project")]
@@ -81,13 +81,13 @@ impl FromStr for GitlabRepository {
         if let Some(suffix) = s.strip_prefix("project-") {
             return suffix.parse::<u64>()
                 .map(|id| GitlabRepository::ProjectId{id})
-                .map_err(|e| GitlabParseError::ParseIntError(e))
+                .map_err(GitlabParseError::ParseIntError)
         }
         Err(GitlabParseError::UnsupportedInput(s.to_string()))
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct GitlabMetadata {
     pub id: GitlabId,
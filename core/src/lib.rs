@@ -1,2 +1,3 @@
+pub mod gitea;
 pub mod gitlab;
 pub mod webhook;
\ No newline at end of file
@@ -1,3 +1,4 @@
+use crate::gitea::GiteaMetadata;
 use crate::gitlab::GitlabMetadata;
 use serde::{Deserialize, Serialize};
 pub use serde_json::Value;
@@ -17,14 +18,23 @@ pub struct GitLogEntry {
     pub message: String,
 }
 
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct DiffStat {
+    pub files_changed: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+#[allow(clippy::result_unit_err)]
 pub fn convert_to_utc_rfc3339(str: &str) -> Result<DateTime<Utc>, ()> {
     iso8601::DateTime::from_str(str)
         .map_err(|_| ())
-        .and_then(|date| chrono::DateTime::<chrono::FixedOffset>::try_from(date))
+        .and_then(chrono::DateTime::<chrono::FixedOffset>::try_from)
         .map(|date| date.to_utc())
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 #[serde(tag = "type")]
 pub enum Change {
@@ -34,6 +44,7 @@ pub enum Change {
         commit: String,
         patch: Option<String>,
         log: Option<Vec<GitLogEntry>>,
+        diff_stat: Option<DiffStat>,
     },
     #[serde(rename = "remove")]
     RemoveRef {
@@ -49,18 +60,20 @@ pub enum Change {
         force: bool,
         patch: Option<String>,
         log: Option<Vec<GitLogEntry>>,
+        diff_stat: Option<DiffStat>,
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 #[serde(tag = "type")]
 pub enum Metadata {
     GitLab(GitlabMetadata),
+    Gitea(GiteaMetadata),
     None,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
 #[serde(rename_all = "kebab-case")]
 pub enum PushSignatureStatus {
     Good,
@@ -111,23 +124,54 @@ pub struct PushSignature {
     pub nonce: CertificateNonce,
 }
 
+pub const PROTOCOL_VERSION: &str = "1";
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "kebab-case")]
 pub struct WebhookRequest {
     pub version: String,
     pub default_branch: String,
+    pub default_branch_commit: Option<String>,
     pub config: Value,
     pub changes: Vec<Change>,
     pub push_options: Vec<String>,
     pub signature: Option<PushSignature>,
     pub metadata: Metadata,
+    #[serde(default)]
+    pub trace: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct WebhookResponse(pub Vec<String>);
 
-impl Default for WebhookResponse {
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebhookAction {
+    Accept,
+    Reject,
+    Continue,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct StructuredWebhookResponse {
+    /// The protocol version the receiver speaks, it must match the version of the request when present.
+    pub version: Option<String>,
+    pub action: Option<WebhookAction>,
+    #[serde(default)]
+    pub messages: Vec<String>,
+}
+
+/// Either the plain list of messages or an object that may also carry an explicit action.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum AnyWebhookResponse {
+    Messages(WebhookResponse),
+    Structured(StructuredWebhookResponse),
+}
+
+impl Default for AnyWebhookResponse {
     fn default() -> Self {
-        WebhookResponse(Vec::default())
+        AnyWebhookResponse::Messages(WebhookResponse::default())
     }
-}
\ No newline at end of file
+}
@@ -53,15 +53,11 @@ async fn validate(req: HttpRequest, body: web::Json<WebhookRequest>) -> impl Res
     }
 
     for file in patch.files() {
-        if let Some(source) = &file.source_file.strip_prefix("a/") {
-            if file_matches(&restricted_regex_pattern, source) {
-                return invalid_reject(source)
-            }
+        if let Some(source) = &file.source_file.strip_prefix("a/") && file_matches(&restricted_regex_pattern, source) {
+            return invalid_reject(source)
         }
-        if let Some(target) = &file.target_file.strip_prefix("b/") {
-            if file_matches(&restricted_regex_pattern, target) {
-                return invalid_reject(target)
-            }
+        if let Some(target) = &file.target_file.strip_prefix("b/") && file_matches(&restricted_regex_pattern, target) {
+            return invalid_reject(target)
         }
     }
 
@@ -97,6 +93,8 @@ fn invalid_reject<T: Display>(file_name: T) -> (web::Json<WebhookResponse>, Stat
 }
 
 const DEFAULT_PORT: u16 = 8080;
+// applies to the decompressed body, gzip encoded requests are decoded by the Json extractor
+const MAX_REQUEST_SIZE: usize = 64 * 1024 * 1024;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -107,7 +105,9 @@ async fn main() -> std::io::Result<()> {
         Ok(s) => s.parse::<u16>().unwrap_or(DEFAULT_PORT),
         Err(_) => DEFAULT_PORT
     };
-    HttpServer::new(|| App::new().service(validate))
+    HttpServer::new(|| App::new()
+        .app_data(web::JsonConfig::default().limit(MAX_REQUEST_SIZE))
+        .service(validate))
         .bind(("0.0.0.0", listen_port))?
         .run()
         .await
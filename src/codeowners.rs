@@ -0,0 +1,56 @@
+use crate::git::path_pattern_to_regex;
+
+pub const DEFAULT_CODEOWNERS_PATHS: [&str; 4] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS", ".gitlab/CODEOWNERS"];
+
+pub struct CodeownersRule {
+    pub pattern: String,
+    pub owners: Vec<String>,
+}
+
+// GitLab section headers like [Documentation] or ^[Optional] are skipped, their rules apply like any other
+pub fn parse_codeowners(content: &str) -> Vec<CodeownersRule> {
+    content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('[') && !line.starts_with("^["))
+        .filter_map(|line| {
+            let mut parts = line.split_ascii_whitespace().take_while(|part| !part.starts_with('#'));
+            let pattern = parts.next()?.to_string();
+            Some(CodeownersRule { pattern, owners: parts.map(str::to_string).collect() })
+        })
+        .collect()
+}
+
+// the last matching rule decides, a path without owners can be changed by anyone
+pub fn owners_of<'a>(rules: &'a [CodeownersRule], path: &str) -> &'a [String] {
+    rules.iter()
+        .rev()
+        .find(|rule| path_pattern_to_regex(rule.pattern.as_str(), true).is_some_and(|regex| regex.is_match(path)))
+        .map(|rule| rule.owners.as_slice())
+        .unwrap_or(&[])
+}
+
+// only user owners can be checked, group owners (@group/subgroup) can't be resolved without the forge's API
+pub fn is_owner(owners: &[String], username: &str) -> bool {
+    owners.iter().any(|owner| owner.strip_prefix('@').is_some_and(|owner| owner.eq_ignore_ascii_case(username)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codeowners_parsing() {
+        let content = "# owners\n* @everyone\n\n[Documentation]\ndocs/ @writer @Lead # docs team\n/src/**/*.rs @rustacean\n/vendor/\n";
+        let rules = parse_codeowners(content);
+        assert_eq!(rules.len(), 4);
+
+        assert_eq!(owners_of(&rules, "README.md"), ["@everyone"]);
+        assert_eq!(owners_of(&rules, "docs/guide/intro.md"), ["@writer", "@Lead"]);
+        assert_eq!(owners_of(&rules, "src/nested/main.rs"), ["@rustacean"]);
+        assert_eq!(owners_of(&rules, "src/build.sh"), ["@everyone"]);
+        assert!(owners_of(&rules, "vendor/lib.c").is_empty());
+
+        assert!(is_owner(owners_of(&rules, "docs/index.md"), "lead"));
+        assert!(!is_owner(owners_of(&rules, "docs/index.md"), "rustacean"));
+    }
+}
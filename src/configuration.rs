@@ -1,56 +1,153 @@
 use crate::rule::Rule;
 use crate::get_absolute_program_path;
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
 use reqwest::Url;
-use serde::de::{Error, Unexpected, Visitor};
+use serde::de::{Error, MapAccess, Unexpected, Visitor};
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
 use serde::{Deserialize, Deserializer};
-use serde_with::serde_as;
+use serde_with::{serde_as, DurationMilliSeconds};
+use std::borrow::Cow;
 use std::fmt::{Debug, Display, Formatter};
+use std::marker::PhantomData;
 use std::path::Path;
+use std::time::Duration;
 
 pub struct Pattern(pub Regex);
 
 struct PatternVisitor;
 
-fn parse_pattern<E>(str: &str) -> Result<Pattern, E>
+fn parse_pattern<E>(str: &str, case_insensitive: bool) -> Result<Pattern, E>
 where
     E: Error
 {
     if str.is_empty() {
         return Err(E::invalid_length(0, &"non-empty regex"));
     }
-    match Regex::new(str) {
+    match RegexBuilder::new(str).case_insensitive(case_insensitive).build() {
         Ok(regex) => Ok(Pattern(regex)),
         Err(err) => Err(E::invalid_value(Unexpected::Str(err.to_string().as_str()), &"a valid regex"))
     }
 }
 
+// Translates a glob into a regex without anchors, callers decide where it has to match: * and ? stay within a
+// path segment, ** spans segments
+pub(crate) fn glob_to_regex_source(glob: &str) -> String {
+    let mut regex = String::new();
+    let mut chars = glob.chars().peekable();
+    let mut in_alternatives = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                regex.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    regex.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+                regex.push(']');
+            }
+            '{' => {
+                in_alternatives = true;
+                regex.push_str("(?:");
+            }
+            ',' if in_alternatives => regex.push('|'),
+            '}' if in_alternatives => {
+                in_alternatives = false;
+                regex.push(')');
+            }
+            c => regex.push_str(regex::escape(c.to_string().as_str()).as_str()),
+        }
+    }
+    regex
+}
+
+fn parse_glob<E>(str: &str, case_insensitive: bool) -> Result<Pattern, E>
+where
+    E: Error
+{
+    if str.is_empty() {
+        return Err(E::invalid_length(0, &"non-empty glob"));
+    }
+    // like in .gitignore, a glob without a slash matches the last segment at any depth, others match from the start
+    let (anchor, glob) = match str.strip_prefix('/') {
+        Some(glob) => ("^", glob),
+        None if str.contains('/') => ("^", str),
+        None => ("^(.*/)?", str),
+    };
+    match RegexBuilder::new(format!("{}{}$", anchor, glob_to_regex_source(glob)).as_str()).case_insensitive(case_insensitive).build() {
+        Ok(regex) => Ok(Pattern(regex)),
+        Err(err) => Err(E::invalid_value(Unexpected::Str(err.to_string().as_str()), &"a valid glob"))
+    }
+}
+
 impl<'de> Visitor<'de> for PatternVisitor {
     type Value = Pattern;
 
     fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
-        formatter.write_str("a valid regex")
+        formatter.write_str("a valid regex or a map with either a glob or a regex")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>
+    {
+        let mut glob = None;
+        let mut regex = None;
+        let mut case_insensitive = false;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "glob" => glob = Some(map.next_value::<String>()?),
+                "regex" => regex = Some(map.next_value::<String>()?),
+                "case-insensitive" => case_insensitive = map.next_value::<bool>()?,
+                other => return Err(A::Error::unknown_field(other, &["glob", "regex", "case-insensitive"])),
+            }
+        }
+        match (glob, regex) {
+            (Some(glob), None) => parse_glob(glob.as_str(), case_insensitive),
+            (None, Some(regex)) => parse_pattern(regex.as_str(), case_insensitive),
+            (Some(_), Some(_)) => Err(A::Error::custom("a pattern must be either a glob or a regex")),
+            (None, None) => Err(A::Error::custom("a pattern needs either a glob or a regex")),
+        }
     }
 
     fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
     where
         E: Error
     {
-        parse_pattern(v)
+        parse_pattern(v, false)
     }
 
     fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Self::Value, E>
     where
         E: Error
     {
-        parse_pattern(v)
+        parse_pattern(v, false)
     }
 
     fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
     where
         E: Error
     {
-        parse_pattern(v.as_str())
+        parse_pattern(v.as_str(), false)
     }
 }
 
@@ -59,14 +156,14 @@ impl <'de> Deserialize<'de> for Pattern {
     where
         D: Deserializer<'de>
     {
-        deserializer.deserialize_str(PatternVisitor)
+        deserializer.deserialize_any(PatternVisitor)
     }
 
     fn deserialize_in_place<D>(deserializer: D, place: &mut Self) -> Result<(), D::Error>
     where
         D: Deserializer<'de>
     {
-        match deserializer.deserialize_str(PatternVisitor) {
+        match deserializer.deserialize_any(PatternVisitor) {
             Ok(pattern) => {
                 *place = pattern;
                 Ok(())
@@ -82,6 +179,47 @@ impl Debug for Pattern {
     }
 }
 
+impl JsonSchema for Pattern {
+    fn schema_name() -> Cow<'static, str> {
+        "Pattern".into()
+    }
+
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "oneOf": [
+                {
+                    "type": "string",
+                    "format": "regex",
+                    "minLength": 1
+                },
+                {
+                    "type": "object",
+                    "properties": {
+                        "regex": { "type": "string", "format": "regex", "minLength": 1 },
+                        "case-insensitive": { "type": "boolean" }
+                    },
+                    "required": ["regex"],
+                    "additionalProperties": false
+                },
+                {
+                    "type": "object",
+                    "properties": {
+                        "glob": {
+                            "type": "string",
+                            "minLength": 1,
+                            "description": "A glob without a slash matches at any depth, others match from the start."
+                        },
+                        "case-insensitive": { "type": "boolean" }
+                    },
+                    "required": ["glob"],
+                    "additionalProperties": false
+                }
+            ]
+        })
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct URL(pub Url);
 
 struct URLVisitor;
@@ -156,13 +294,60 @@ impl Debug for URL {
     }
 }
 
+impl JsonSchema for URL {
+    fn schema_name() -> Cow<'static, str> {
+        "URL".into()
+    }
+
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "string",
+            "format": "uri",
+            "minLength": 1
+        })
+    }
+}
+
+// NonEmpty has no schema of its own, its fields refer to this with #[schemars(with = "...")]
+pub struct NonEmptySchema<T>(PhantomData<T>);
+
+impl<T: JsonSchema> JsonSchema for NonEmptySchema<T> {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        format!("NonEmpty_Array_of_{}", T::schema_name()).into()
+    }
+
+    fn schema_id() -> Cow<'static, str> {
+        format!("NonEmpty<{}>", T::schema_id()).into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "array",
+            "minItems": 1,
+            "items": generator.subschema_for::<T>()
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum HookType {
     PreReceive,
     Update,
     PostReceive,
 }
 
-#[derive(Debug, Deserialize)]
+impl HookType {
+    // post-receive runs after the refs have been updated and git ignores its exit code
+    pub fn can_reject(&self) -> bool {
+        !matches!(self, HookType::PostReceive)
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(rename_all = "kebab-case")]
 pub struct HookBypass {
     pub push_option: String,
@@ -170,14 +355,16 @@ pub struct HookBypass {
 }
 
 #[serde_as]
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(rename_all = "kebab-case")]
 pub struct Hook {
     pub rule: Rule,
     pub reject_on_error: Option<bool>,
+    pub max_scanned_commits: Option<usize>,
 }
 
-#[derive(Debug, Deserialize)]
+#[serde_as]
+#[derive(Debug, Default, Deserialize, JsonSchema)]
 #[serde(rename_all = "kebab-case")]
 pub struct ConfigurationVersion1 {
     pub pre_receive: Option<Hook>,
@@ -185,17 +372,53 @@ pub struct ConfigurationVersion1 {
     pub update: Option<Hook>,
     pub bypass: Option<HookBypass>,
     pub trace: Option<bool>,
+    pub max_depth: Option<u8>,
+    pub config_source: Option<ConfigSource>,
+    pub diff_range: Option<DiffRange>,
+    /// Wall-clock budget for evaluating all changes of a push, including webhook requests.
+    #[serde_as(as = "Option<DurationMilliSeconds<u64>>")]
+    pub max_total_duration: Option<Duration>,
 }
 
-impl ConfigurationVersion1 {
-    pub(crate) fn trace<T: Display>(&self, line: T, depth: u8) {
-        if self.trace.unwrap_or(false) {
-            eprintln!("trace: {}> {}", "-".repeat(depth.into()), line);
+/// Where additional checks for changes to branches other than the default branch are read from.
+/// A pushed ref's configuration only adds its hook's rule, the default branch's hooks and all other options stay in force.
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConfigSource {
+    DefaultBranch,
+    PushedRef,
+}
+
+/// How the patch, file status and diff stat of an update are computed.
+/// This decides which files the file conditions see when a force-push drops commits.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiffRange {
+    /// `old..new`: everything that differs between both commits, including the changes of dropped commits.
+    #[default]
+    TwoDot,
+    /// `old...new`: only what the new commit adds relative to the merge base, like code review tools show it.
+    ThreeDot,
+}
+
+impl DiffRange {
+    pub fn range(&self, old_commit: &str, new_commit: &str) -> String {
+        match self {
+            DiffRange::TwoDot => format!("{}..{}", old_commit, new_commit),
+            DiffRange::ThreeDot => format!("{}...{}", old_commit, new_commit),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+const DEFAULT_MAX_DEPTH: u8 = 64;
+
+impl ConfigurationVersion1 {
+    pub(crate) fn max_depth(&self) -> u8 {
+        self.max_depth.unwrap_or(DEFAULT_MAX_DEPTH)
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(rename_all = "kebab-case")]
 #[serde(tag = "version")]
 pub enum Configuration {
@@ -203,18 +426,133 @@ pub enum Configuration {
     Version1(ConfigurationVersion1)
 }
 
+impl Configuration {
+    pub fn schema() -> Schema {
+        schemars::schema_for!(Configuration)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match self {
+            Configuration::Version1(v1) => {
+                if let Some(ref bypass) = v1.bypass && bypass.push_option.is_empty() {
+                    return Err(ConfigError::Validation("bypass push-option must not be empty".to_string()));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    pub fn parse(&self, file: &str, content: &str) -> Result<Configuration, ConfigError> {
+        match self {
+            ConfigFormat::Yaml => serde_yml::from_str(content).map_err(|err| ConfigError::Parse {
+                file: file.to_string(),
+                format: *self,
+                position: err.location().map(|l| ConfigPosition { line: l.line(), column: l.column() }),
+                message: err.to_string(),
+            }),
+            ConfigFormat::Toml => toml::from_str(content).map_err(|err| ConfigError::Parse {
+                file: file.to_string(),
+                format: *self,
+                position: err.span().map(|span| ConfigPosition::from_offset(content, span.start)),
+                message: err.message().to_string(),
+            }),
+            ConfigFormat::Json => serde_json::from_str(content).map_err(|err| ConfigError::Parse {
+                file: file.to_string(),
+                format: *self,
+                position: Some(ConfigPosition { line: err.line(), column: err.column() }),
+                message: err.to_string(),
+            }),
+        }
+    }
+}
+
+impl Display for ConfigFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigFormat::Yaml => write!(f, "YAML"),
+            ConfigFormat::Toml => write!(f, "TOML"),
+            ConfigFormat::Json => write!(f, "JSON"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConfigPosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl ConfigPosition {
+    fn from_offset(content: &str, offset: usize) -> ConfigPosition {
+        let before = &content[..offset.min(content.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+        ConfigPosition { line, column }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    GitRead(String),
+    Parse {
+        file: String,
+        format: ConfigFormat,
+        position: Option<ConfigPosition>,
+        message: String,
+    },
+    Validation(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::GitRead(err) => {
+                write!(f, "unable to read configuration: {}", err)
+            }
+            ConfigError::Parse { file, format, position: Some(ConfigPosition { line, column }), message } => {
+                write!(f, "unable to parse {} as {} at line {} column {}: {}", file, format, line, column, message)
+            }
+            ConfigError::Parse { file, format, position: None, message } => {
+                write!(f, "unable to parse {} as {}: {}", file, format, message)
+            }
+            ConfigError::Validation(msg) => {
+                write!(f, "invalid configuration: {}", msg)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
 impl ConfigurationVersion1 {
+    pub fn hook(&self, hook_type: HookType) -> Option<&Hook> {
+        match hook_type {
+            HookType::PreReceive => self.pre_receive.as_ref(),
+            HookType::Update => self.update.as_ref(),
+            HookType::PostReceive => self.post_receive.as_ref(),
+        }
+    }
+
     pub fn select_hook(&self) -> Option<(&Hook, HookType)> {
         let exe_path = match get_absolute_program_path() {
             Ok(path) => path,
             Err(_) => return None
         };
-        let by_name = hook_by_executable_name(&self, &exe_path);
+        let by_name = hook_by_executable_name(self, &exe_path);
         if by_name.is_some() {
             return by_name;
         }
 
-        let by_parent = hook_by_parent_dir_name(&self, &exe_path);
+        let by_parent = hook_by_parent_dir_name(self, &exe_path);
         if by_parent.is_some() {
             return by_parent;
         }
@@ -238,25 +576,138 @@ fn hook_by_parent_dir_name<'a>(configuration: &'a ConfigurationVersion1, path: &
 }
 
 fn hook_by_name<'a>(configuration: &'a ConfigurationVersion1, name: &str) -> Option<(&'a Hook, HookType)> {
-    match name {
-        "pre-receive" => {
-            match &configuration.pre_receive {
-                Some(h) => Some((h, HookType::PreReceive)),
-                None => None
-            }
-        },
-        "update" => {
-            match &configuration.update {
-                Some(h) => Some((h, HookType::Update)),
-                None => None
+    let hook_type = match name {
+        "pre-receive" => HookType::PreReceive,
+        "update" => HookType::Update,
+        "post-receive" => HookType::PostReceive,
+        _ => return None,
+    };
+    configuration.hook(hook_type).map(|h| (h, hook_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use super::*;
+
+    #[test]
+    fn test_config_error_variants() {
+        let git_read = ConfigError::GitRead("invalid utf-8".to_string());
+        assert_eq!(git_read.to_string(), "unable to read configuration: invalid utf-8");
+
+        let toml = indoc! {r#"
+            version = "1"
+            trace = maybe
+        "#};
+        match ConfigFormat::Toml.parse("hooks.toml", toml) {
+            Err(ConfigError::Parse { file, format, position, .. }) => {
+                assert_eq!(file, "hooks.toml");
+                assert_eq!(format, ConfigFormat::Toml);
+                assert_eq!(position, Some(ConfigPosition { line: 2, column: 9 }));
             }
-        },
-        "post-receive" => {
-            match &configuration.post_receive {
-                Some(h) => Some((h, HookType::PostReceive)),
-                None => None
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        let yaml = indoc! {r#"
+            version: "1"
+            trace: [
+        "#};
+        match ConfigFormat::Yaml.parse("hooks.yaml", yaml) {
+            Err(ConfigError::Parse { format, position, .. }) => {
+                assert_eq!(format, ConfigFormat::Yaml);
+                assert!(position.is_some());
             }
-        },
-        _ => None,
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        let invalid = ConfigFormat::Yaml.parse("hooks.yaml", indoc! {r#"
+            version: "1"
+            bypass:
+              push-option: ""
+        "#}).expect("configuration should parse");
+        match invalid.validate() {
+            Err(ConfigError::Validation(msg)) => assert_eq!(msg, "bypass push-option must not be empty"),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_pattern_forms() {
+        #[derive(Deserialize)]
+        struct Patterns {
+            bare: Pattern,
+            regex: Pattern,
+            glob: Pattern,
+        }
+
+        let yaml: Patterns = serde_yml::from_str(indoc! {r#"
+            bare: "^refs/heads/.+$"
+            regex: { regex: "\\.rs$" }
+            glob: { glob: "src/**/*.{rs,toml}" }
+        "#}).expect("patterns should parse");
+        assert!(yaml.bare.0.is_match("refs/heads/main"));
+        assert!(yaml.regex.0.is_match("src/main.rs"));
+        assert!(yaml.glob.0.is_match("src/main.rs"));
+        assert!(yaml.glob.0.is_match("src/nested/Cargo.toml"));
+        assert!(!yaml.glob.0.is_match("tests/main.rs"));
+        assert!(!yaml.glob.0.is_match("src/main.rs.orig"));
+
+        let toml: Patterns = toml::from_str(indoc! {r#"
+            bare = "^v[0-9]+$"
+            regex = { regex = "^v[0-9]+$" }
+            glob = { glob = "refs/tags/v?.[0-9]*" }
+        "#}).expect("patterns should parse");
+        assert!(toml.glob.0.is_match("refs/tags/v1.2"));
+        assert!(!toml.glob.0.is_match("refs/tags/v1.x"));
+
+        let glob = |glob: &str| serde_yml::from_str::<Pattern>(format!("glob: \"{}\"", glob).as_str()).expect("glob should parse").0;
+        assert!(glob("*.rs").is_match("src/main.rs"));
+        assert!(glob("*.rs").is_match("main.rs"));
+        assert!(!glob("*.rs").is_match("src/main.rs.orig"));
+        assert!(glob("/*.rs").is_match("main.rs"));
+        assert!(!glob("/*.rs").is_match("src/main.rs"));
+        assert!(!glob("docs/*.md").is_match("old/docs/README.md"));
+
+        assert!(serde_yml::from_str::<Patterns>("bare: a\nregex: { regex: a, glob: b }\nglob: { glob: a }").is_err());
+        assert!(serde_yml::from_str::<Patterns>("bare: a\nregex: { pattern: a }\nglob: { glob: a }").is_err());
+    }
+
+    #[test]
+    fn test_case_insensitive_pattern() {
+        #[derive(Deserialize)]
+        struct Patterns {
+            regex: Pattern,
+            glob: Pattern,
+            sensitive: Pattern,
+        }
+
+        let patterns: Patterns = serde_yml::from_str(indoc! {r#"
+            regex: { regex: "^refs/heads/feature/", case-insensitive: true }
+            glob: { glob: "docs/*.md", case-insensitive: true }
+            sensitive: { regex: "^refs/heads/feature/", case-insensitive: false }
+        "#}).expect("patterns should parse");
+        assert!(patterns.regex.0.is_match("refs/heads/Feature/login"));
+        assert!(patterns.glob.0.is_match("Docs/README.MD"));
+        assert!(!patterns.sensitive.0.is_match("refs/heads/Feature/login"));
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema = serde_json::to_value(Configuration::schema()).expect("schema should serialize");
+        let definitions = &schema["$defs"];
+        let types = |definition: &str| definitions[definition]["oneOf"].as_array().into_iter()
+            .chain(definitions[definition]["anyOf"].as_array())
+            .flatten()
+            .filter_map(|variant| variant["properties"]["type"]["const"].as_str())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        assert_eq!(schema["oneOf"][0]["properties"]["version"]["const"], "1");
+        assert!(types("Condition").contains(&"ref-is".to_string()));
+        assert!(types("Condition").contains(&"push-option-matches".to_string()));
+        assert!(types("Rule").contains(&"parallel-webhook".to_string()));
+        assert!(definitions["Hook"]["properties"]["max-scanned-commits"].is_object());
+        assert!(definitions["WebhookRule"]["properties"]["request-timeout"]["type"].as_array().is_some_and(|t| t.contains(&"integer".into())));
+        assert_eq!(definitions["HookBypass"]["required"], serde_json::json!(["push-option"]));
     }
 }
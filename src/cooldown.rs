@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// stored in the repository's git directory, so that the times are kept per repository
+pub const PUSH_TIMES_FILE: &str = "webbed_hook-push-times";
+
+fn read_push_times(file: &mut File) -> std::io::Result<HashMap<String, u64>> {
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    Ok(content.lines()
+        .filter_map(|line| {
+            let (time, ref_name) = line.split_once(' ')?;
+            Some((ref_name.to_string(), time.parse().ok()?))
+        })
+        .collect())
+}
+
+fn write_push_times(file: &mut File, times: &HashMap<String, u64>) -> std::io::Result<()> {
+    let content = times.iter()
+        .map(|(ref_name, time)| format!("{} {}\n", time, ref_name))
+        .collect::<String>();
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(content.as_bytes())
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default()
+}
+
+// The push times of a repository, locked exclusively from the check until the push is recorded or rejected.
+// Concurrent pushes therefore wait for each other instead of all passing the check before any of them is recorded.
+#[derive(Debug)]
+pub struct PushTimes {
+    file: File,
+    times: HashMap<String, u64>,
+}
+
+impl PushTimes {
+    pub fn lock(state_file: &Path) -> std::io::Result<PushTimes> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(state_file)?;
+        file.lock()?;
+        let times = read_push_times(&mut file)?;
+        Ok(PushTimes { file, times })
+    }
+
+    pub fn elapsed(&self, ref_name: &str, now: u64, seconds: u64) -> bool {
+        self.times.get(ref_name).is_none_or(|last| now.saturating_sub(*last) >= seconds)
+    }
+
+    // the lock is released once the push times are dropped
+    pub fn record(mut self, ref_names: &[String], now: u64) -> std::io::Result<()> {
+        for ref_name in ref_names {
+            self.times.insert(ref_name.clone(), now);
+        }
+        write_push_times(&mut self.file, &self.times)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::test_repo::{current_dir, TestRepo};
+    use std::time::Duration;
+
+    #[test]
+    fn test_cooldown_elapsed() {
+        let _repo = TestRepo::new();
+        let state_file = current_dir().expect("test repository should be set").join(PUSH_TIMES_FILE);
+        let lock = || PushTimes::lock(&state_file).expect("state should be lockable");
+        let elapsed = |ref_name: &str, now: u64| lock().elapsed(ref_name, now, 60);
+        let record = |ref_name: &str, now: u64| lock().record(&[ref_name.to_string()], now).expect("state should be writable");
+
+        assert!(elapsed("refs/heads/main", 1000));
+        record("refs/heads/main", 1000);
+        assert!(!elapsed("refs/heads/main", 1030));
+        assert!(elapsed("refs/heads/feature", 1030));
+        record("refs/heads/feature", 1030);
+        assert!(elapsed("refs/heads/main", 1060));
+        record("refs/heads/main", 1060);
+        assert!(!elapsed("refs/heads/main", 1119));
+        assert!(!elapsed("refs/heads/feature", 1089));
+
+        std::fs::write(&state_file, "garbage\n1000 refs/heads/other\n").expect("state should be writable");
+        assert!(elapsed("refs/heads/main", 1100));
+        record("refs/heads/main", 1100);
+        assert!(!elapsed("refs/heads/main", 1101));
+        assert!(!elapsed("refs/heads/other", 1010));
+    }
+
+    #[test]
+    fn test_concurrent_pushes_wait_for_each_other() {
+        let _repo = TestRepo::new();
+        let state_file = current_dir().expect("test repository should be set").join(PUSH_TIMES_FILE);
+
+        let first = PushTimes::lock(&state_file).expect("state should be lockable");
+        assert!(first.elapsed("refs/heads/main", 1000, 60));
+        let second = {
+            let state_file = state_file.clone();
+            std::thread::spawn(move || PushTimes::lock(&state_file).expect("state should be lockable").elapsed("refs/heads/main", 1001, 60))
+        };
+        // the second push can't pass the check while the first one is still being evaluated
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!second.is_finished());
+        first.record(&["refs/heads/main".to_string()], 1000).expect("state should be writable");
+        assert!(!second.join().expect("second push should finish"));
+    }
+}
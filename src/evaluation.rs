@@ -0,0 +1,97 @@
+use crate::cooldown::PushTimes;
+use std::fmt::Display;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Runtime state of evaluating a push, shared by all of its changes. The configuration stays plain data.
+#[derive(Debug, Default)]
+pub struct EvaluationState {
+    budget: Option<Duration>,
+    deadline: Option<Instant>,
+    explain: bool,
+    trace_lines: Mutex<Vec<String>>,
+    explain_lines: Mutex<Vec<String>>,
+    cooldown: Mutex<Option<(PushTimes, Vec<String>)>>,
+}
+
+impl EvaluationState {
+    // the clock starts right away, the budget is owned by the default branch's configuration
+    pub fn new(max_total_duration: Option<Duration>, explain: bool) -> Self {
+        EvaluationState {
+            budget: max_total_duration,
+            deadline: max_total_duration.map(|budget| Instant::now() + budget),
+            explain,
+            ..EvaluationState::default()
+        }
+    }
+
+    pub fn explain(&self) -> bool {
+        self.explain
+    }
+
+    pub fn budget(&self) -> Option<Duration> {
+        self.budget
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn remaining_time(&self) -> Option<Duration> {
+        self.deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    pub fn trace<T: Display>(&self, line: T, depth: u8) {
+        let line = format!("{}> {}", "-".repeat(depth.into()), line);
+        eprintln!("trace: {}", line);
+        self.trace_lines.lock().expect("trace lines poisoned").push(line);
+    }
+
+    pub fn trace_lines(&self) -> Vec<String> {
+        self.trace_lines.lock().expect("trace lines poisoned").clone()
+    }
+
+    // reserves a line before evaluating, so that parents are listed above their children
+    pub fn explain_begin(&self) -> Option<usize> {
+        if self.explain {
+            let mut lines = self.explain_lines.lock().expect("explain lines poisoned");
+            lines.push(String::new());
+            Some(lines.len() - 1)
+        } else {
+            None
+        }
+    }
+
+    pub fn explain_end<T: Display>(&self, slot: usize, depth: u8, line: T) {
+        self.explain_lines.lock().expect("explain lines poisoned")[slot] = format!("{}{}", "  ".repeat(depth.into()), line);
+    }
+
+    pub fn explain_note<T: Display>(&self, depth: u8, line: T) {
+        if self.explain {
+            self.explain_lines.lock().expect("explain lines poisoned").push(format!("{}{}", "  ".repeat(depth.into()), line));
+        }
+    }
+
+    pub fn take_explanation(&self) -> Vec<String> {
+        std::mem::take(&mut *self.explain_lines.lock().expect("explain lines poisoned"))
+    }
+
+    // The push times stay locked until the push is recorded or the hook exits, the checked refs are recorded once the
+    // push went through.
+    pub fn cooldown_elapsed(&self, state_file: &Path, ref_name: &str, now: u64, seconds: u64) -> std::io::Result<bool> {
+        let mut cooldown = self.cooldown.lock().expect("cooldown poisoned");
+        let (times, refs) = match &mut *cooldown {
+            Some(cooldown) => cooldown,
+            None => cooldown.insert((PushTimes::lock(state_file)?, Vec::new())),
+        };
+        if !refs.iter().any(|r| r == ref_name) {
+            refs.push(ref_name.to_string());
+        }
+        Ok(times.elapsed(ref_name, now, seconds))
+    }
+
+    pub fn take_cooldown(&self) -> Option<(PushTimes, Vec<String>)> {
+        self.cooldown.lock().expect("cooldown poisoned").take()
+    }
+}
@@ -1,22 +1,87 @@
+use crate::configuration::{glob_to_regex_source, DiffRange};
+use regex::Regex;
+use std::collections::HashMap;
 use std::ffi::OsStr;
-use std::io::{BufRead, Error, Lines};
+use std::io::{BufRead, Error, ErrorKind, Lines, Read};
+use std::path::PathBuf;
 use std::process::{Command, Output, Stdio};
 use std::str::FromStr;
-use webbed_hook_core::webhook::{convert_to_utc_rfc3339, DateTime, GitLogEntry, Utc};
+use std::sync::{LazyLock, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use webbed_hook_core::webhook::{convert_to_utc_rfc3339, DateTime, DiffStat, GitLogEntry, Utc};
 
 const MULTILINE_INDENT: usize = 4;
 
-fn run_git_command<I, S>(args: I) -> Result<Option<Output>, Error>
+fn git_command<I, S>(args: I) -> Command
 where
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
 {
-    Command::new("git")
+    let mut command = Command::new("git");
+    #[cfg(test)]
+    if let Some(dir) = test_repo::current_dir() {
+        command.current_dir(dir);
+    }
+    command
         .args(args)
         .stdout(Stdio::piped())
         .stderr(Stdio::null())
-        .stdin(Stdio::null())
-        .output()
+        .stdin(Stdio::null());
+    command
+}
+
+// Set once the time budget of the hook starts, git commands still running past it are killed.
+static DEADLINE: OnceLock<Instant> = OnceLock::new();
+
+pub fn set_deadline(deadline: Instant) {
+    let _ = DEADLINE.set(deadline);
+}
+
+// like Command::output, stderr is discarded
+fn output_before(mut command: Command, deadline: Option<Instant>) -> Result<Output, Error> {
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return command.output(),
+    };
+    let mut child = command.spawn()?;
+    // the pipe is drained concurrently, otherwise a large output blocks the child forever
+    let stdout = child.stdout.take();
+    let reader = std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut stdout) = stdout {
+            stdout.read_to_end(&mut buffer)?;
+        }
+        Ok::<_, Error>(buffer)
+    });
+    loop {
+        if let Some(status) = child.try_wait()? {
+            let stdout = reader.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+            return Ok(Output { status, stdout, stderr: vec![] });
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Err(Error::new(ErrorKind::TimedOut, "git command exceeded the time budget"));
+        }
+        std::thread::sleep((deadline - now).min(Duration::from_millis(5)));
+    }
+}
+
+fn git_output<I, S>(args: I) -> Result<Output, Error>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    output_before(git_command(args), DEADLINE.get().copied())
+}
+
+fn run_git_command<I, S>(args: I) -> Result<Option<Output>, Error>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    git_output(args)
         .map(|output| {
             if output.status.success() {
                 Some(output)
@@ -26,6 +91,34 @@ where
         })
 }
 
+fn git_stdout(args: &[&str]) -> Option<String> {
+    run_git_command(args)
+        .ok()
+        .flatten()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+}
+
+// the working directory and the arguments of a query
+type QueryKey = (Option<PathBuf>, Vec<String>);
+
+// Answers of small queries that conditions repeat and that can't change during a hook run.
+static QUERY_CACHE: LazyLock<Mutex<HashMap<QueryKey, String>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// only answers are kept, a failed query is attempted again
+fn cached_query<F: FnOnce(&[&str]) -> Option<String>>(args: &[&str], query: F) -> Option<String> {
+    #[cfg(test)]
+    let dir = test_repo::current_dir();
+    #[cfg(not(test))]
+    let dir = None;
+    let key = (dir, args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>());
+    if let Some(answer) = QUERY_CACHE.lock().expect("query cache poisoned").get(&key) {
+        return Some(answer.clone());
+    }
+    let answer = query(args)?;
+    QUERY_CACHE.lock().expect("query cache poisoned").insert(key, answer.clone());
+    Some(answer)
+}
+
 fn parse_indented_multiline_string(lines: &mut Lines<&[u8]>) -> String {
     let mut message = String::new();
     while let Some(Ok(ref line)) = lines.next() {
@@ -44,14 +137,8 @@ fn parse_single_optional_line(lines: &mut Lines<&[u8]>) -> Result<Option<String>
     match lines.next() {
         Some(line) => line
             .map_err(|err| err.to_string())
-            .map(|line| {
-                println!("some line: {}", line);
-                if line.is_empty() { None } else { Some(line) }
-            }),
-        None => {
-            println!("no line!");
-            Err("no more lines".to_string())
-        },
+            .map(|line| if line.is_empty() { None } else { Some(line) }),
+        None => Err("no more lines".to_string()),
     }
 }
 
@@ -68,23 +155,14 @@ fn parse_single_date_line(lines: &mut Lines<&[u8]>) -> Result<DateTime<Utc>, Str
     })
 }
 
-fn parse_lines_until_empty(lines: &mut Lines<&[u8]>) -> Vec<String> {
-    let mut output: Vec<String> = Vec::new();
-    loop {
-        match lines.next() {
-            Some(Ok(line)) => {
-                if line.is_empty() {
-                    break
-                } else {
-                    output.push(line);
-                }
-            }
-            _ => {
-                break
-            }
-        }
-    }
-    output
+// %P lists all parents on a single line, followed by the empty line separating them from the author
+fn parse_parents_line(lines: &mut Lines<&[u8]>) -> Result<Vec<String>, String> {
+    let parents = parse_single_line(lines)?
+        .split_ascii_whitespace()
+        .map(str::to_string)
+        .collect();
+    parse_single_line(lines)?;
+    Ok(parents)
 }
 
 fn parse_log_entry(lines: &mut Lines<&[u8]>) -> Result<Option<GitLogEntry>, String> {
@@ -101,7 +179,7 @@ fn parse_log_entry(lines: &mut Lines<&[u8]>) -> Result<Option<GitLogEntry>, Stri
     }
 
     let hash = parse_single_line(lines)?;
-    let parents = parse_lines_until_empty(lines);
+    let parents = parse_parents_line(lines)?;
     let author = parse_single_line(lines)?;
     let author_date = parse_single_date_line(lines)?;
     let committer = parse_single_line(lines)?;
@@ -134,21 +212,57 @@ fn parse_log(lines: &mut Lines<&[u8]>) -> Vec<GitLogEntry> {
     output
 }
 
-pub fn git_show_file_from_default_branch(file: &str) -> Result<Option<String>, String> {
-    run_git_command(["show", format!("HEAD:{}", file).as_str()])
+fn parse_trailer_line(line: &str) -> Option<(String, String)> {
+    let (key, value) = line.split_once(':')?;
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return None;
+    }
+    Some((key.to_string(), value.trim().to_string()))
+}
+
+pub fn parse_trailers(message: &str) -> Vec<(String, String)> {
+    let paragraphs = message.trim_end()
+        .split("\n\n")
+        .collect::<Vec<_>>();
+    if paragraphs.len() < 2 {
+        return vec![];
+    }
+
+    let mut trailers: Vec<(String, String)> = Vec::new();
+    for line in paragraphs[paragraphs.len() - 1].lines() {
+        if line.starts_with(char::is_whitespace) {
+            match trailers.last_mut() {
+                Some((_, value)) => {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                    continue
+                }
+                None => return vec![],
+            }
+        }
+        match parse_trailer_line(line) {
+            Some(trailer) => trailers.push(trailer),
+            None => return vec![],
+        }
+    }
+    trailers
+}
+
+pub fn git_show_file_from_rev(rev: &str, file: &str) -> Result<Option<String>, String> {
+    run_git_command(["show", format!("{}:{}", rev, file).as_str()])
         .map_err(|err| err.to_string())
         .and_then(|output| {
             match output {
                 Some(output) => String::from_utf8(output.stdout)
-                    .map(|s| Some(s))
+                    .map(Some)
                     .map_err(|err| format!("invalid utf-8: {}", err).to_string()),
                 None => Ok(None)
             }
         })
 }
 
-pub fn diff(old_commit: &str, new_commit: &str) -> Option<String> {
-    run_git_command(["diff", format!("{}..{}", old_commit, new_commit).as_str()])
+pub fn diff(old_commit: &str, new_commit: &str, range: DiffRange) -> Option<String> {
+    run_git_command(["diff", range.range(old_commit, new_commit).as_str()])
         .ok()
         .flatten()
         .and_then(|output| String::from_utf8(output.stdout).ok())
@@ -157,10 +271,10 @@ pub fn diff(old_commit: &str, new_commit: &str) -> Option<String> {
 #[derive(PartialEq, Debug)]
 pub enum FileStatus {
     Added,
-    Copied,
+    Copied { source: String },
     Deleted,
     Modified,
-    Renamed,
+    Renamed { source: String },
     TypeChanged,
     Unmerged,
     Unknown,
@@ -173,10 +287,8 @@ impl FromStr for FileStatus {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "A" => Ok(FileStatus::Added),
-            "C" => Ok(FileStatus::Copied),
             "D" => Ok(FileStatus::Deleted),
             "M" => Ok(FileStatus::Modified),
-            "R" => Ok(FileStatus::Renamed),
             "T" => Ok(FileStatus::TypeChanged),
             "U" => Ok(FileStatus::Unmerged),
             "X" => Ok(FileStatus::Unknown),
@@ -186,24 +298,35 @@ impl FromStr for FileStatus {
     }
 }
 
+// Renames and copies carry a similarity score (e.g. R100) and list the source before the destination path.
+fn parse_name_status_line(line: &str) -> Option<(FileStatus, String)> {
+    let mut iter = line.trim().split_ascii_whitespace();
+    let status = iter.next()?;
+    let letter = status.get(..1)?;
+    if !status[1..].chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let path = iter.next()?.to_string();
+    let entry = match letter {
+        "R" => (FileStatus::Renamed { source: path }, iter.next()?.to_string()),
+        "C" => (FileStatus::Copied { source: path }, iter.next()?.to_string()),
+        _ => (FileStatus::from_str(letter).ok()?, path),
+    };
+    if iter.next().is_some() {
+        None
+    } else {
+        Some(entry)
+    }
+}
+
 fn parse_name_status<T: Iterator<Item=Result<String, Error>>>(lines: &mut T) -> Vec<(FileStatus, String)> {
     lines
-        .filter_map(|line| {
-            let line = line.ok()?;
-            let mut iter = line.trim().split_ascii_whitespace();
-            let status = FileStatus::from_str(iter.next()?).ok()?;
-            let name = iter.next()?;
-            if let Some(_) = iter.next() {
-                None
-            } else {
-                Some((status, name.to_string()))
-            }
-        })
+        .filter_map(|line| parse_name_status_line(line.ok()?.as_str()))
         .collect::<Vec<_>>()
 }
 
-pub fn diff_name_status(old_commit: &str, new_commit: &str) -> Vec<(FileStatus, String)> {
-    run_git_command(["diff", "--name-status", format!("{}..{}", old_commit, new_commit).as_str()])
+pub fn diff_name_status(old_commit: &str, new_commit: &str, range: DiffRange) -> Vec<(FileStatus, String)> {
+    run_git_command(["diff", "--name-status", range.range(old_commit, new_commit).as_str()])
         .ok()
         .flatten()
         .map(|output| {
@@ -213,13 +336,32 @@ pub fn diff_name_status(old_commit: &str, new_commit: &str) -> Vec<(FileStatus,
         .unwrap_or_default()
 }
 
-pub fn merge_base(old_commit: &str, new_commit: &str) -> Option<String> {
-    run_git_command(vec!["merge-base", old_commit, new_commit])
+// binary files are listed as "-\t-\tpath" and only count as a changed file
+fn parse_numstat(output: &str) -> Option<DiffStat> {
+    let mut stat = DiffStat::default();
+    for line in output.lines().filter(|l| !l.is_empty()) {
+        let mut parts = line.splitn(3, '\t');
+        let insertions = parts.next()?;
+        let deletions = parts.next()?;
+        parts.next()?;
+        stat.files_changed += 1;
+        stat.insertions += insertions.parse::<usize>().unwrap_or(0);
+        stat.deletions += deletions.parse::<usize>().unwrap_or(0);
+    }
+    Some(stat)
+}
+
+pub fn diff_stat(old_commit: &str, new_commit: &str, range: DiffRange) -> Option<DiffStat> {
+    run_git_command(["diff", "--numstat", range.range(old_commit, new_commit).as_str()])
         .ok()
         .flatten()
-        .and_then(|output| {
-            String::from_utf8(output.stdout).map(|s| s.as_str().trim().to_string()).ok()
-        })
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|output| parse_numstat(output.as_str()))
+}
+
+pub fn merge_base(old_commit: &str, new_commit: &str) -> Option<String> {
+    cached_query(&["merge-base", old_commit, new_commit], git_stdout)
+        .map(|base| base.trim().to_string())
 }
 
 fn git_log(args: Vec<&str>) -> Vec<GitLogEntry> {
@@ -236,14 +378,229 @@ fn git_log(args: Vec<&str>) -> Vec<GitLogEntry> {
         .unwrap_or_default()
 }
 
-pub fn git_log_for_range(from: &str, to: &str) -> Vec<GitLogEntry> {
-    git_log(vec![format!("{}..{}", from, to).as_str()])
+pub fn git_log_for_range(from: &str, to: &str, limit: Option<usize>) -> Vec<GitLogEntry> {
+    let range = format!("{}..{}", from, to);
+    match limit {
+        Some(limit) => git_log(vec![format!("--max-count={}", limit).as_str(), range.as_str()]),
+        None => git_log(vec![range.as_str()]),
+    }
 }
 
 pub fn git_log_limited(limit: u32, to: &str) -> Vec<GitLogEntry> {
     git_log(vec![format!("--max-count={}", limit).as_str(), to])
 }
 
+pub fn count_commits(from: Option<&str>, to: &str) -> Option<usize> {
+    let range = match from {
+        Some(from) => format!("{}..{}", from, to),
+        None => to.to_string(),
+    };
+    cached_query(&["rev-list", "--count", range.as_str()], git_stdout)
+        .and_then(|count| count.trim().parse::<usize>().ok())
+}
+
+pub fn is_ancestor(ancestor: &str, descendant: &str) -> Option<bool> {
+    let answer = cached_query(&["merge-base", "--is-ancestor", ancestor, descendant], |args| {
+        match git_output(args).ok()?.status.code() {
+            Some(0) => Some(true.to_string()),
+            Some(1) => Some(false.to_string()),
+            _ => None,
+        }
+    });
+    answer.map(|answer| answer == "true")
+}
+
+pub fn rev_list(include: &str, exclude: &str) -> Option<Vec<String>> {
+    run_git_command(["rev-list", include, format!("^{}", exclude).as_str()])
+        .ok()
+        .flatten()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commits| commits.lines().map(str::to_string).collect())
+}
+
+// the %G? status of every commit in the range, G is a good signature
+pub fn signature_statuses(from: &str, to: &str) -> Option<Vec<(String, String)>> {
+    run_git_command(["log", "--format=%H %G?", format!("{}..{}", from, to).as_str()])
+        .ok()
+        .flatten()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|statuses| statuses.lines()
+            .filter_map(|line| line.split_once(' '))
+            .map(|(commit, status)| (commit.to_string(), status.to_string()))
+            .collect())
+}
+
+pub fn ref_exists(name: &str) -> bool {
+    matches!(run_git_command(["show-ref", "--verify", "--quiet", name]), Ok(Some(_)))
+}
+
+pub fn rev_parse(rev: &str) -> Option<String> {
+    run_git_command(["rev-parse", "--verify", "--quiet", rev])
+        .ok()
+        .flatten()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim_end().to_string())
+}
+
+// the id of the empty tree depends on the object format of the repository
+pub fn empty_tree() -> Option<String> {
+    run_git_command(["hash-object", "-t", "tree", "/dev/null"])
+        .ok()
+        .flatten()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|tree| tree.trim_end().to_string())
+}
+
+pub fn git_dir() -> Option<PathBuf> {
+    run_git_command(["rev-parse", "--absolute-git-dir"])
+        .ok()
+        .flatten()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|dir| PathBuf::from(dir.trim_end()))
+}
+
+pub fn is_object_id(hash: &str, length: usize) -> bool {
+    hash.len() == length && hash.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+// the length of a hex object id in this repository, 40 for SHA-1 and 64 for SHA-256
+pub fn object_id_length() -> Option<usize> {
+    let output = run_git_command(["rev-parse", "--show-object-format"]).ok().flatten()?;
+    match String::from_utf8(output.stdout).ok()?.trim_end() {
+        "sha1" => Some(40),
+        "sha256" => Some(64),
+        _ => None,
+    }
+}
+
+pub fn show_blob(commit: &str, path: &str) -> Option<Vec<u8>> {
+    run_git_command(["cat-file", "blob", format!("{}:{}", commit, path).as_str()])
+        .ok()
+        .flatten()
+        .map(|output| output.stdout)
+}
+
+pub fn blob_size(commit: &str, path: &str) -> Option<u64> {
+    run_git_command(["cat-file", "-s", format!("{}:{}", commit, path).as_str()])
+        .ok()
+        .flatten()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|size| size.trim().parse::<u64>().ok())
+}
+
+pub fn list_tree(commit: &str) -> Option<Vec<String>> {
+    run_git_command(["ls-tree", "-r", "-z", "--name-only", commit])
+        .ok()
+        .flatten()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|paths| paths.split('\0').filter(|p| !p.is_empty()).map(str::to_string).collect())
+}
+
+pub struct GitattributesLine {
+    pub pattern: String,
+    pub attributes: Vec<String>,
+}
+
+pub fn parse_gitattributes(content: &str) -> Vec<GitattributesLine> {
+    content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut parts = line.split_ascii_whitespace();
+            let pattern = parts.next()?.to_string();
+            Some(GitattributesLine { pattern, attributes: parts.map(str::to_string).collect() })
+        })
+        .collect()
+}
+
+// Patterns without a slash match the file name at any depth, all others are relative to the repository root.
+// A trailing slash only matches directories, match_contents lets any pattern match the files below a directory.
+pub(crate) fn path_pattern_to_regex(pattern: &str, match_contents: bool) -> Option<Regex> {
+    let (pattern, directory_only) = match pattern.strip_suffix('/') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let anchored = pattern.contains('/');
+    let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+    let mut regex = String::from(if anchored { "^" } else { "(^|/)" });
+    regex.push_str(glob_to_regex_source(pattern).as_str());
+    if directory_only {
+        regex.push_str("/.*");
+    } else if match_contents {
+        regex.push_str("(/.*)?");
+    }
+    regex.push('$');
+    Regex::new(regex.as_str()).ok()
+}
+
+impl GitattributesLine {
+    pub fn matches(&self, path: &str) -> bool {
+        path_pattern_to_regex(self.pattern.as_str(), false).is_some_and(|regex| regex.is_match(path))
+    }
+}
+
+// the last matching line that mentions the attribute decides its value
+pub fn gitattribute_value<'a>(lines: &'a [GitattributesLine], path: &str, attribute: &str) -> Option<&'a str> {
+    let prefix = format!("{}=", attribute);
+    lines.iter()
+        .rev()
+        .filter(|line| line.matches(path))
+        .flat_map(|line| line.attributes.iter().rev())
+        .find_map(|attr| {
+            if let Some(value) = attr.strip_prefix(prefix.as_str()) {
+                Some(Some(value))
+            } else if attr == attribute {
+                Some(Some("true"))
+            } else if attr.strip_prefix(['-', '!']) == Some(attribute) {
+                Some(None)
+            } else {
+                None
+            }
+        })
+        .flatten()
+}
+
+pub fn object_type(object: &str) -> Option<String> {
+    run_git_command(["cat-file", "-t", object])
+        .ok()
+        .flatten()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|kind| kind.trim_end().to_string())
+}
+
+// git verify-tag fails for unsigned tags just like for bad signatures, so only the tag object itself is checked upfront
+pub fn verify_tag(tag: &str) -> Option<bool> {
+    if object_type(tag)? != "tag" {
+        return Some(false);
+    }
+    let output = git_output(["verify-tag", tag]).ok()?;
+    Some(output.status.success())
+}
+
+pub fn has_note(notes_ref: &str, commit: &str) -> Option<bool> {
+    let output = git_output(["notes", format!("--ref={}", notes_ref).as_str(), "show", commit]).ok()?;
+    if output.status.success() {
+        Some(true)
+    } else if rev_parse(format!("{}^{{commit}}", commit).as_str()).is_some() {
+        // git notes show fails without distinguishing a missing note from other errors
+        Some(false)
+    } else {
+        None
+    }
+}
+
+pub fn path_exists_at(commit: &str, path: &str) -> Option<bool> {
+    let status = git_output(["cat-file", "-e", format!("{}:{}", commit, path).as_str()]).ok()?.status;
+    if status.success() {
+        Some(true)
+    } else if rev_parse(format!("{}^{{commit}}", commit).as_str()).is_some() {
+        // cat-file -e fails the same way for a missing path and a missing commit
+        Some(false)
+    } else {
+        None
+    }
+}
+
 pub fn get_default_branch() -> Option<String> {
     run_git_command(["rev-parse", "--abbrev-ref", "HEAD"])
         .ok()
@@ -252,6 +609,96 @@ pub fn get_default_branch() -> Option<String> {
         .map(|branch_name| branch_name.trim_end().to_string())
 }
 
+#[cfg(test)]
+pub mod test_repo {
+    use std::cell::RefCell;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process::Command;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static REPO_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    thread_local! {
+        static CURRENT_DIR: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+    }
+
+    pub fn current_dir() -> Option<PathBuf> {
+        CURRENT_DIR.with(|dir| dir.borrow().clone())
+    }
+
+    // A throwaway repository that git helpers called from the same test thread operate on.
+    pub struct TestRepo {
+        path: PathBuf,
+    }
+
+    impl TestRepo {
+        pub fn new() -> TestRepo {
+            let n = REPO_COUNTER.fetch_add(1, Ordering::SeqCst);
+            let path = std::env::temp_dir().join(format!("webbed_hook-test-{}-{}", std::process::id(), n));
+            fs::create_dir_all(&path).expect("failed to create test repository");
+            let repo = TestRepo { path };
+            repo.git(&["init", "--quiet", "--initial-branch=main"]);
+            repo.git(&["config", "user.name", "John Doe"]);
+            repo.git(&["config", "user.email", "jdoe@example.org"]);
+            repo.git(&["config", "commit.gpgsign", "false"]);
+            CURRENT_DIR.with(|dir| *dir.borrow_mut() = Some(repo.path.clone()));
+            repo
+        }
+
+        pub fn git(&self, args: &[&str]) -> String {
+            let output = Command::new("git")
+                .current_dir(&self.path)
+                .args(args)
+                .output()
+                .expect("failed to run git");
+            assert!(output.status.success(), "git {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+            String::from_utf8(output.stdout).expect("git output should be utf-8").trim_end().to_string()
+        }
+
+        pub fn write(&self, file: &str, content: &[u8]) {
+            let path = self.path.join(file);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).expect("failed to create directory");
+            }
+            fs::write(path, content).expect("failed to write file");
+        }
+
+        pub fn commit(&self, files: &[(&str, &str)], message: &str) -> String {
+            for (file, content) in files {
+                self.write(file, content.as_bytes());
+            }
+            self.git(&["add", "--all"]);
+            self.git(&["commit", "--quiet", "--allow-empty", "--message", message]);
+            self.git(&["rev-parse", "HEAD"])
+        }
+
+        // signs with a fresh SSH key that is also trusted for verification, the key stays out of the work tree
+        pub fn enable_signing(&self) {
+            let key = self.path.join(".git").join("signing-key");
+            let output = Command::new("ssh-keygen")
+                .args(["-q", "-t", "ed25519", "-N", "", "-C", "jdoe@example.org", "-f"])
+                .arg(&key)
+                .output()
+                .expect("failed to run ssh-keygen");
+            assert!(output.status.success(), "ssh-keygen failed: {}", String::from_utf8_lossy(&output.stderr));
+            let public_key = fs::read_to_string(key.with_extension("pub")).expect("failed to read the public key");
+            let allowed_signers = self.path.join(".git").join("allowed_signers");
+            fs::write(&allowed_signers, format!("jdoe@example.org {}", public_key)).expect("failed to write allowed signers");
+            self.git(&["config", "gpg.format", "ssh"]);
+            self.git(&["config", "user.signingkey", key.to_str().expect("key path should be utf-8")]);
+            self.git(&["config", "gpg.ssh.allowedSignersFile", allowed_signers.to_str().expect("path should be utf-8")]);
+        }
+    }
+
+    impl Drop for TestRepo {
+        fn drop(&mut self) {
+            CURRENT_DIR.with(|dir| *dir.borrow_mut() = None);
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use indoc::indoc;
@@ -270,6 +717,8 @@ mod tests {
             M       src/main.rs
             A       src/rule.rs
             M       src/webhook.rs
+            R100    old.txt new.txt
+            C75     template.txt copy.txt
         "};
 
         let mut line_iter = name_status_text.lines().map(|s| Ok(s.to_owned()));
@@ -285,7 +734,219 @@ mod tests {
             (FileStatus::Modified, "src/main.rs".to_owned()),
             (FileStatus::Added, "src/rule.rs".to_owned()),
             (FileStatus::Modified, "src/webhook.rs".to_owned()),
+            (FileStatus::Renamed { source: "old.txt".to_owned() }, "new.txt".to_owned()),
+            (FileStatus::Copied { source: "template.txt".to_owned() }, "copy.txt".to_owned()),
         ];
         assert_eq!(actual, expected);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_trailer_parsing() {
+        let message = indoc! {"
+            Fix the thing
+
+            Some explanation: with a colon.
+
+            Signed-off-by: John Doe <jdoe@example.org>
+            Co-authored-by: Jane Roe
+              <jroe@example.org>
+        "};
+        let expected = vec![
+            ("Signed-off-by".to_owned(), "John Doe <jdoe@example.org>".to_owned()),
+            ("Co-authored-by".to_owned(), "Jane Roe <jroe@example.org>".to_owned()),
+        ];
+        assert_eq!(parse_trailers(message), expected);
+
+        assert_eq!(parse_trailers("Subject: not a trailer"), vec![]);
+        assert_eq!(parse_trailers("Subject\n\nJust a body."), vec![]);
+    }
+
+    #[test]
+    fn test_rev_parse() {
+        let repo = test_repo::TestRepo::new();
+        let first = repo.commit(&[("README.md", "hello")], "Initial commit");
+        let second = repo.commit(&[("README.md", "hello world")], "Update readme");
+
+        assert_eq!(rev_parse("main"), Some(second));
+        assert_eq!(rev_parse("main~1"), Some(first));
+        assert_eq!(rev_parse("does-not-exist"), None);
+    }
+
+    #[test]
+    fn test_count_commits() {
+        let repo = test_repo::TestRepo::new();
+        let first = repo.commit(&[("README.md", "hello")], "Initial commit");
+        repo.commit(&[("README.md", "hello world")], "Update readme");
+        let third = repo.commit(&[("README.md", "hello again")], "Update readme again");
+
+        assert_eq!(count_commits(Some(first.as_str()), third.as_str()), Some(2));
+        assert_eq!(count_commits(None, third.as_str()), Some(3));
+        assert_eq!(count_commits(None, "does-not-exist"), None);
+    }
+
+    #[test]
+    fn test_ref_exists() {
+        let repo = test_repo::TestRepo::new();
+        repo.commit(&[("README.md", "hello")], "Initial commit");
+        repo.git(&["tag", "v1.0.0"]);
+
+        assert!(ref_exists("refs/heads/main"));
+        assert!(ref_exists("refs/tags/v1.0.0"));
+        assert!(!ref_exists("refs/heads/feature"));
+    }
+
+    #[test]
+    fn test_ancestry() {
+        let repo = test_repo::TestRepo::new();
+        let first = repo.commit(&[("README.md", "1")], "First");
+        let second = repo.commit(&[("README.md", "2")], "Second");
+        let third = repo.commit(&[("README.md", "3")], "Third");
+
+        assert_eq!(is_ancestor(first.as_str(), third.as_str()), Some(true));
+        assert_eq!(is_ancestor(third.as_str(), first.as_str()), Some(false));
+        assert_eq!(is_ancestor("does-not-exist", third.as_str()), None);
+        assert_eq!(rev_list(third.as_str(), first.as_str()), Some(vec![third, second]));
+    }
+
+    #[test]
+    fn test_list_tree() {
+        let repo = test_repo::TestRepo::new();
+        let commit = repo.commit(&[("README.md", "hello"), ("src/main.rs", "fn main() {}")], "Initial commit");
+
+        assert_eq!(list_tree(commit.as_str()), Some(vec!["README.md".to_string(), "src/main.rs".to_string()]));
+        assert_eq!(list_tree("does-not-exist"), None);
+    }
+
+    #[test]
+    fn test_numstat_parsing() {
+        let output = "3\t1\tsrc/main.rs\n-\t-\tlogo.png\n10\t0\tdocs/new file.md\n";
+        assert_eq!(parse_numstat(output), Some(DiffStat { files_changed: 3, insertions: 13, deletions: 1 }));
+        assert_eq!(parse_numstat(""), Some(DiffStat::default()));
+        assert_eq!(parse_numstat("garbage"), None);
+    }
+
+    #[test]
+    fn test_gitattributes_parsing() {
+        let content = "# binaries\n*.png filter=lfs diff=lfs merge=lfs -text\n/assets/** filter=lfs\nassets/readme.txt -filter\ndocs/*.psd filter=lfs\n";
+        let lines = parse_gitattributes(content);
+        assert_eq!(lines.len(), 4);
+
+        assert_eq!(gitattribute_value(&lines, "logo.png", "filter"), Some("lfs"));
+        assert_eq!(gitattribute_value(&lines, "img/nested/logo.png", "filter"), Some("lfs"));
+        assert_eq!(gitattribute_value(&lines, "assets/video/intro.mp4", "filter"), Some("lfs"));
+        assert_eq!(gitattribute_value(&lines, "assets/readme.txt", "filter"), None);
+        assert_eq!(gitattribute_value(&lines, "docs/cover.psd", "filter"), Some("lfs"));
+        assert_eq!(gitattribute_value(&lines, "docs/nested/cover.psd", "filter"), None);
+        assert_eq!(gitattribute_value(&lines, "logo.png", "text"), None);
+        assert_eq!(gitattribute_value(&lines, "src/main.rs", "filter"), None);
+    }
+
+    #[test]
+    fn test_notes() {
+        let repo = test_repo::TestRepo::new();
+        let annotated = repo.commit(&[("README.md", "1")], "First");
+        let plain = repo.commit(&[("README.md", "2")], "Second");
+        repo.git(&["notes", "--ref=provenance", "add", "-m", "attestation", annotated.as_str()]);
+
+        assert_eq!(has_note("provenance", annotated.as_str()), Some(true));
+        assert_eq!(has_note("provenance", plain.as_str()), Some(false));
+        assert_eq!(has_note("other", annotated.as_str()), Some(false));
+        assert_eq!(has_note("provenance", "does-not-exist"), None);
+    }
+
+    #[test]
+    fn test_log_parents() {
+        let repo = test_repo::TestRepo::new();
+        let root = repo.commit(&[("README.md", "1")], "Root");
+        repo.git(&["checkout", "--quiet", "-b", "feature"]);
+        let feature = repo.commit(&[("feature.txt", "1")], "Feature");
+        repo.git(&["checkout", "--quiet", "main"]);
+        let main = repo.commit(&[("README.md", "2")], "Main");
+        repo.git(&["merge", "--quiet", "--no-edit", "feature"]);
+        let merge = repo.git(&["rev-parse", "HEAD"]);
+
+        let log = git_log_limited(10, "HEAD");
+        let parents = |hash: &str| log.iter().find(|e| e.hash == hash).map(|e| e.parents.clone());
+        assert_eq!(log.len(), 4);
+        assert_eq!(parents(&merge), Some(vec![main.clone(), feature.clone()]));
+        assert_eq!(parents(&main), Some(vec![root.clone()]));
+        assert_eq!(parents(&root), Some(vec![]));
+        assert!(log.iter().all(|e| e.author == "John Doe <jdoe@example.org>"));
+    }
+
+    #[test]
+    fn test_sha256_repository() {
+        let repo = test_repo::TestRepo::new();
+        assert_eq!(object_id_length(), Some(40));
+        repo.git(&["config", "core.repositoryformatversion", "1"]);
+        repo.git(&["config", "extensions.objectformat", "sha256"]);
+        assert_eq!(object_id_length(), Some(64));
+
+        let first = repo.commit(&[("README.md", "1")], "First");
+        let second = repo.commit(&[("README.md", "2")], "Second");
+        assert!(is_object_id(&second, 64));
+        assert!(!is_object_id(&second, 40));
+
+        let log = git_log_for_range(&first, &second, None);
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].hash, second);
+        assert_eq!(log[0].parents, vec![first]);
+    }
+
+    #[test]
+    fn test_diff_range() {
+        let repo = test_repo::TestRepo::new();
+        let base = repo.commit(&[("README.md", "1")], "Initial commit");
+        let old = repo.commit(&[("dropped.txt", "dropped\n")], "Dropped by the force-push");
+        repo.git(&["checkout", "--quiet", "-b", "rewritten", base.as_str()]);
+        let new = repo.commit(&[("added.txt", "added\n")], "Rewritten");
+
+        let names = |range: DiffRange| diff_name_status(&old, &new, range);
+        assert_eq!(names(DiffRange::TwoDot), vec![(FileStatus::Added, "added.txt".to_string()), (FileStatus::Deleted, "dropped.txt".to_string())]);
+        assert_eq!(names(DiffRange::ThreeDot), vec![(FileStatus::Added, "added.txt".to_string())]);
+        assert_eq!(diff_stat(&old, &new, DiffRange::ThreeDot).map(|stat| stat.files_changed), Some(1));
+        assert!(diff(&old, &new, DiffRange::ThreeDot).is_some_and(|patch| !patch.contains("dropped.txt")));
+
+        let config: crate::configuration::ConfigurationVersion1 = serde_yml::from_str("diff-range: three-dot").expect("config should parse");
+        assert_eq!(config.diff_range, Some(DiffRange::ThreeDot));
+    }
+
+    #[test]
+    fn test_command_cache() {
+        let repo = test_repo::TestRepo::new();
+        let first = repo.commit(&[("README.md", "first")], "First");
+        let second = repo.commit(&[("README.md", "second")], "Second");
+        assert_eq!(merge_base(&first, &second), Some(first.clone()));
+        assert_eq!(is_ancestor(&first, &second), Some(true));
+        assert_eq!(is_ancestor(&second, &first), Some(false));
+
+        // with the repository gone only the cached queries still answer
+        let dir = test_repo::current_dir().expect("test repository should be set");
+        std::fs::rename(dir.join(".git"), dir.join(".git-moved")).expect("git directory should be movable");
+        assert_eq!(merge_base(&first, &second), Some(first.clone()));
+        assert_eq!(is_ancestor(&first, &second), Some(true));
+        assert_eq!(is_ancestor(&second, &first), Some(false));
+        assert_eq!(count_commits(None, &second), None);
+        assert_eq!(diff(&first, &second, DiffRange::TwoDot), None);
+        std::fs::rename(dir.join(".git-moved"), dir.join(".git")).expect("git directory should be movable");
+
+        // failures are not remembered
+        assert_eq!(count_commits(None, &second), Some(2));
+    }
+
+    #[test]
+    fn test_output_before_deadline() {
+        let mut sleep = Command::new("sleep");
+        sleep.arg("5").stdout(Stdio::piped());
+        let started = Instant::now();
+        let result = output_before(sleep, Some(started + Duration::from_millis(100)));
+        assert!(matches!(result, Err(ref err) if err.kind() == ErrorKind::TimedOut));
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        let mut echo = Command::new("echo");
+        echo.arg("hello").stdout(Stdio::piped());
+        let output = output_before(echo, Some(Instant::now() + Duration::from_secs(5))).expect("echo should finish in time");
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"hello\n");
+    }
+}
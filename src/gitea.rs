@@ -0,0 +1,53 @@
+use crate::util::env_as;
+use std::env;
+use webbed_hook_core::gitea::GiteaMetadata;
+
+pub fn get_gitea_metadata() -> Option<GiteaMetadata> {
+    let pusher_id = env_as::<u64>("GITEA_PUSHER_ID")?;
+    let pusher_name = env::var("GITEA_PUSHER_NAME").ok()?;
+    let pusher_email = env::var("GITEA_PUSHER_EMAIL").ok().filter(|email| !email.is_empty());
+    let repository_id = env_as::<u64>("GITEA_REPO_ID")?;
+    let repository_owner = env::var("GITEA_REPO_USER_NAME").ok()?;
+    let repository_name = env::var("GITEA_REPO_NAME").ok()?;
+    // only set when the push merges a pull request
+    let pull_request_id = env_as::<u64>("GITEA_PR_ID").filter(|id| *id != 0);
+
+    Some(GiteaMetadata {
+        pusher_id,
+        pusher_name,
+        pusher_email,
+        repository_id,
+        repository_owner,
+        repository_name,
+        pull_request_id,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_gathering() {
+        unsafe {
+            env::set_var("GITEA_PUSHER_ID", "42");
+            env::set_var("GITEA_PUSHER_NAME", "some-user");
+            env::set_var("GITEA_PUSHER_EMAIL", "some-user@example.org");
+            env::set_var("GITEA_REPO_ID", "7");
+            env::set_var("GITEA_REPO_USER_NAME", "some-org");
+            env::set_var("GITEA_REPO_NAME", "some-repo");
+            env::set_var("GITEA_PR_ID", "0");
+        }
+
+        let expected = GiteaMetadata {
+            pusher_id: 42,
+            pusher_name: "some-user".to_string(),
+            pusher_email: Some("some-user@example.org".to_string()),
+            repository_id: 7,
+            repository_owner: "some-org".to_string(),
+            repository_name: "some-repo".to_string(),
+            pull_request_id: None,
+        };
+        assert_eq!(get_gitea_metadata(), Some(expected));
+    }
+}
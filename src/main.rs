@@ -2,28 +2,36 @@ mod configuration;
 mod webhook;
 mod util;
 mod gitlab;
+mod gitea;
 mod git;
 mod rule;
+mod codeowners;
+mod cooldown;
+mod evaluation;
 
 use std::cell::LazyCell;
-use crate::rule::{RuleAction, RuleContext, RuleResult};
-use crate::configuration::{Configuration, HookBypass, HookType};
-use crate::git::{diff, diff_name_status, get_default_branch, git_log_for_range, git_log_limited, git_show_file_from_default_branch, merge_base, FileStatus};
+use crate::cooldown::unix_now;
+use crate::evaluation::EvaluationState;
+use crate::rule::{Rule, RuleAction, RuleContext, RuleResult};
+use crate::configuration::{ConfigError, ConfigFormat, ConfigSource, Configuration, ConfigurationVersion1, DiffRange, Hook, HookBypass, HookType};
+use crate::git::{set_deadline, diff, diff_name_status, diff_stat, get_default_branch, git_log_for_range, git_log_limited, git_show_file_from_rev, is_object_id, merge_base, object_id_length, FileStatus};
 use crate::util::env_as;
+use crate::webhook::{get_metadata, get_push_signature};
 use path_clean::PathClean;
 use std::env;
-use std::error::Error;
 use std::fmt::Display;
 use std::io::BufRead;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::process::exit;
-use webbed_hook_core::webhook::{GitLogEntry};
+use webbed_hook_core::webhook::{DiffStat, GitLogEntry, Metadata, PushSignature};
 
 pub struct GitData {
     pub patch: Box<dyn Deref<Target=Option<String>>>,
     pub log: Box<dyn Deref<Target=Vec<GitLogEntry>>>,
     pub file_status: Box<dyn Deref<Target=Vec<(FileStatus, String)>>>,
+    pub diff_stat: Box<dyn Deref<Target=Option<DiffStat>>>,
+    pub log_limit: Option<usize>,
 }
 
 pub enum Change {
@@ -63,10 +71,10 @@ pub struct ChangeLine {
     pub ref_name: String,
 }
 
+#[allow(clippy::lines_filter_map_ok)]
 fn read_changes_from_stdin() -> Option<Vec<ChangeLine>> {
     let stdin = std::io::stdin();
     let changes = stdin.lock().lines()
-        .into_iter()
         .filter_map(|line| line.ok())
         .map(|line| {
             let parts = line.split(' ').collect::<Vec<_>>();
@@ -110,47 +118,64 @@ fn get_changes(hook_type: HookType) -> Option<Vec<ChangeLine>> {
     }
 }
 
-fn is_hash_all_zeros(hash: &str) -> bool {
-    hash.chars().all(|c| c == '0')
+// falls back to accepting both SHA-1 and SHA-256 lengths when the object format can't be determined
+fn is_hash_all_zeros(hash: &str, length: Option<usize>) -> bool {
+    let valid = match length {
+        Some(length) => is_object_id(hash, length),
+        None => is_object_id(hash, 40) || is_object_id(hash, 64),
+    };
+    valid && hash.chars().all(|c| c == '0')
+}
+
+fn lazy_diff(old_commit: &str, new_commit: &str, range: DiffRange) -> Box<dyn Deref<Target=Option<String>>> {
+    let old_commit = old_commit.to_owned();
+    let new_commit = new_commit.to_owned();
+
+    Box::new(LazyCell::new(move || diff(old_commit.as_str(), new_commit.as_str(), range)))
 }
 
-fn lazy_diff(old_commit: &str, new_commit: &str) -> Box<dyn Deref<Target=Option<String>>> {
+fn lazy_file_status(old_commit: &str, new_commit: &str, range: DiffRange) -> Box<dyn Deref<Target=Vec<(FileStatus, String)>>> {
     let old_commit = old_commit.to_owned();
     let new_commit = new_commit.to_owned();
 
-    Box::new(LazyCell::new(move || diff(old_commit.as_str(), new_commit.as_str())))
+    Box::new(LazyCell::new(move || diff_name_status(old_commit.as_str(), new_commit.as_str(), range)))
 }
 
-fn lazy_file_status(old_commit: &str, new_commit: &str) -> Box<dyn Deref<Target=Vec<(FileStatus, String)>>> {
+fn lazy_diff_stat(old_commit: &str, new_commit: &str, range: DiffRange) -> Box<dyn Deref<Target=Option<DiffStat>>> {
     let old_commit = old_commit.to_owned();
     let new_commit = new_commit.to_owned();
 
-    Box::new(LazyCell::new(move || diff_name_status(old_commit.as_str(), new_commit.as_str())))
+    Box::new(LazyCell::new(move || diff_stat(old_commit.as_str(), new_commit.as_str(), range)))
 }
 
-fn lazy_log(base: &Option<String>, new_commit: &str) -> Box<dyn Deref<Target=Vec<GitLogEntry>>> {
+fn lazy_log(base: &Option<String>, new_commit: &str, limit: Option<usize>) -> Box<dyn Deref<Target=Vec<GitLogEntry>>> {
     let new_commit = new_commit.to_owned();
+    // one more entry than the limit is loaded to detect when the limit is exceeded
+    let max_count = limit.map(|limit| limit.saturating_add(1));
     match base {
         Some(base) => {
             let base = base.to_owned();
-            Box::new(LazyCell::new(move || git_log_for_range(base.as_str(), new_commit.as_str())))
+            Box::new(LazyCell::new(move || git_log_for_range(base.as_str(), new_commit.as_str(), max_count)))
         },
         None => {
-            Box::new(LazyCell::new(move || git_log_limited(100, new_commit.as_str())))
+            let max_count = max_count.map_or(100, |count| count.min(100) as u32);
+            Box::new(LazyCell::new(move || git_log_limited(max_count, new_commit.as_str())))
         }
     }
 }
 
-fn resolve_change(line: ChangeLine, default_branch: &str) -> Option<Change> {
-    let old_exists = !is_hash_all_zeros(&line.old_commit);
-    let new_exists = !is_hash_all_zeros(&line.new_commit);
-    let patch = lazy_diff(&line.old_commit, &line.new_commit);
-    let file_status = lazy_file_status(&line.old_commit, &line.new_commit);
+fn resolve_change(line: ChangeLine, default_branch: &str, range: DiffRange, log_limit: Option<usize>) -> Option<Change> {
+    let length = object_id_length();
+    let old_exists = !is_hash_all_zeros(&line.old_commit, length);
+    let new_exists = !is_hash_all_zeros(&line.new_commit, length);
+    let patch = lazy_diff(&line.old_commit, &line.new_commit, range);
+    let file_status = lazy_file_status(&line.old_commit, &line.new_commit, range);
+    let diff_stat = lazy_diff_stat(&line.old_commit, &line.new_commit, range);
 
     match (old_exists, new_exists) {
         (true, true) => {
             let merge_base = merge_base(&line.old_commit, &line.new_commit);
-            let log = lazy_log(&merge_base, &line.new_commit);
+            let log = lazy_log(&merge_base, &line.new_commit, log_limit);
             let force = match merge_base {
                 Some(ref base) => base != &line.old_commit,
                 None => true
@@ -159,6 +184,8 @@ fn resolve_change(line: ChangeLine, default_branch: &str) -> Option<Change> {
                 patch,
                 log,
                 file_status,
+                diff_stat,
+                log_limit,
             };
             Some(Change::UpdateRef {
                 name: line.ref_name,
@@ -175,11 +202,13 @@ fn resolve_change(line: ChangeLine, default_branch: &str) -> Option<Change> {
         }),
         (false, true) => {
             let merge_base = merge_base(default_branch, &line.new_commit);
-            let log = lazy_log(&merge_base, &line.new_commit);
+            let log = lazy_log(&merge_base, &line.new_commit, log_limit);
             let git_data = GitData {
                 patch,
                 log,
                 file_status,
+                diff_stat,
+                log_limit,
             };
             Some(Change::AddRef {
                 name: line.ref_name,
@@ -192,9 +221,9 @@ fn resolve_change(line: ChangeLine, default_branch: &str) -> Option<Change> {
 
 }
 
-fn resolve_changes(changes: Vec<ChangeLine>, default_branch: &str) -> Vec<Change> {
+fn resolve_changes(changes: Vec<ChangeLine>, default_branch: &str, range: DiffRange, log_limit: Option<usize>) -> Vec<Change> {
     changes.into_iter()
-        .filter_map(|line| resolve_change(line, default_branch))
+        .filter_map(|line| resolve_change(line, default_branch, range, log_limit))
         .collect()
 }
 
@@ -219,59 +248,191 @@ fn get_push_options() -> Vec<String> {
     }).collect()
 }
 
-fn attempt_bypass(options: &Vec<String>, bypass: &Option<HookBypass>) {
-    if let Some(bypass) = bypass {
-        if options.contains(&bypass.push_option) {
-            if let Some(ref messages) = bypass.messages {
-                for line in messages {
-                    println!("{}", line)
-                }
+fn attempt_bypass(options: &[String], bypass: &Option<HookBypass>) {
+    if let Some(bypass) = bypass && options.contains(&bypass.push_option) {
+        if let Some(ref messages) = bypass.messages {
+            for line in messages {
+                println!("{}", line)
             }
-            exit(0)
         }
+        exit(0)
     }
 }
 
-fn load_config<E: Error, T: FnOnce(&str) -> Result<Configuration, E>>(name: &str, parse: T) -> Result<Option<Configuration>, String> {
-    git_show_file_from_default_branch(name)
-        .and_then(|content| {
-            match content {
-                Some(content) => parse(content.as_str())
-                    .map(|c| Some(c))
-                    .map_err(|err| err.to_string()),
-                None => Ok(None)
-            }
-        })
+fn load_config(rev: &str, name: &str, format: ConfigFormat) -> Result<Option<Configuration>, ConfigError> {
+    match git_show_file_from_rev(rev, name).map_err(ConfigError::GitRead)? {
+        Some(content) => {
+            let configuration = format.parse(name, content.as_str())?;
+            configuration.validate()?;
+            Ok(Some(configuration))
+        }
+        None => Ok(None)
+    }
 }
 
-fn load_config_from_default_branch() -> Result<Option<Configuration>, String> {
-    if let Some(yaml) = load_config("hooks.yaml", |s| serde_yml::from_str(s))? {
+// The first file found wins, a file that exists but fails to load is an error instead of falling through.
+fn load_config_from_rev(rev: &str) -> Result<Option<Configuration>, ConfigError> {
+    if let Some(yaml) = load_config(rev, "hooks.yaml", ConfigFormat::Yaml)? {
         return Ok(Some(yaml))
     }
-    if let Some(yaml) = load_config("hooks.yml", |s| serde_yml::from_str(s))? {
+    if let Some(yaml) = load_config(rev, "hooks.yml", ConfigFormat::Yaml)? {
         return Ok(Some(yaml))
     }
-    if let Some(toml) = load_config("hooks.toml", |s| toml::from_str(s))? {
+    if let Some(toml) = load_config(rev, "hooks.toml", ConfigFormat::Toml)? {
         return Ok(Some(toml))
     }
+    if let Some(json) = load_config(rev, "hooks.json", ConfigFormat::Json)? {
+        return Ok(Some(json))
+    }
     Ok(None)
 }
 
-fn accept<T: Display>(messages: Vec<T>) {
+fn load_config_from_default_branch() -> Result<Option<Configuration>, ConfigError> {
+    load_config_from_rev("HEAD")
+}
+
+// The default branch is always checked by its own configuration. A pushed ref without a usable
+// configuration falls back to the default branch's, so that deleting the file doesn't disable the checks.
+fn load_config_from_pushed_ref(change: &Change, default_branch: &str) -> Option<ConfigurationVersion1> {
+    let commit = match change {
+        Change::AddRef { commit, .. } => commit,
+        Change::UpdateRef { new_commit, .. } => new_commit,
+        Change::RemoveRef { .. } => return None,
+    };
+    if change.ref_name() == format!("refs/heads/{}", default_branch) {
+        return None;
+    }
+    match load_config_from_rev(commit) {
+        Ok(Some(Configuration::Version1(config))) => Some(config),
+        Ok(None) => None,
+        Err(err) => {
+            eprintln!("Failed to load hook configuration from {}, using the default branch's: {}", change.ref_name(), err);
+            None
+        }
+    }
+}
+
+fn accept<T: Display>(messages: Vec<T>) -> bool {
     for msg in messages {
         println!("{}", msg);
     }
-    exit(0);
+    true
 }
 
-fn reject<T: Display>(messages: Vec<T>) {
+fn reject<T: Display>(messages: Vec<T>) -> bool {
     for msg in messages {
         eprintln!("{}", msg);
     }
-    exit(1);
+    false
+}
+
+fn evaluate_change(rule: &Rule, reject_on_err: bool, ctx: &RuleContext, can_reject: bool) -> bool {
+    match rule.evaluate(ctx, 0) {
+        Ok(RuleResult { action, messages }) => {
+            match action {
+                RuleAction::Accept => accept(messages),
+                RuleAction::Continue => accept(messages),
+                RuleAction::Reject => reject(messages),
+            }
+        }
+        Err(err) => {
+            if !can_reject {
+                reject(vec![format!("evaluation failed: {}", err)])
+            } else if reject_on_err {
+                reject(vec![format!("change rejected, evaluation failed: {}", err)])
+            } else {
+                accept(vec![format!("change accepted, but evaluation failed: {}", err)])
+            }
+        }
+    }
+}
+
+fn explain(change: &Change, accepted: bool, lines: Vec<String>) {
+    println!("explain: {} would be {}", change.ref_name(), if accepted { "accepted" } else { "rejected" });
+    for line in lines {
+        println!("explain: {}", line);
+    }
+}
+
+// the inputs shared by all changes of a push
+struct Push<'a> {
+    default_branch: &'a str,
+    push_options: &'a [String],
+    changes: &'a [Change],
+    metadata: &'a Metadata,
+    push_signature: Option<&'a PushSignature>,
+}
+
+// Every change is evaluated so that all messages are reported, a single rejection rejects the push.
+// Returns the refs that were rejected.
+fn evaluate_push(config: &ConfigurationVersion1, hook: &Hook, hook_type: HookType, push: &Push, state: &EvaluationState) -> Vec<String> {
+    let can_reject = hook_type.can_reject();
+    // the default branch's error handling also applies to the rules of pushed refs
+    let reject_on_err = hook.reject_on_error.unwrap_or(true);
+    let mut rejected = Vec::new();
+    for change in push.changes {
+        let pushed_config = match config.config_source {
+            Some(ConfigSource::PushedRef) => load_config_from_pushed_ref(change, push.default_branch),
+            _ => None,
+        };
+
+        let ctx = RuleContext {
+            default_branch: push.default_branch,
+            push_options: push.push_options,
+            change,
+            changes: push.changes,
+            config,
+            metadata: push.metadata,
+            push_signature: push.push_signature,
+            state,
+        };
+
+        // a pushed ref can only add checks, so both rules are evaluated and either can reject
+        let mut accepted = evaluate_change(&hook.rule, reject_on_err, &ctx, can_reject);
+        match pushed_config.as_ref().and_then(|c| c.hook(hook_type)) {
+            // requests would run with the server's environment, TLS files and network access on behalf of the pusher
+            Some(pushed_hook) if !pushed_hook.rule.is_local() => {
+                println!("Ignoring the hook configuration of {}, only the default branch's configuration may make requests", change.ref_name());
+            }
+            Some(pushed_hook) => accepted &= evaluate_change(&pushed_hook.rule, reject_on_err, &ctx, can_reject),
+            None => {}
+        }
+        if !accepted {
+            rejected.push(change.ref_name().to_string());
+        }
+        if state.explain() {
+            explain(change, accepted, state.take_explanation());
+        }
+    }
+    rejected
+}
+
+fn record_cooldowns(state: &EvaluationState) {
+    if let Some((times, refs)) = state.take_cooldown() && let Err(err) = times.record(&refs, unix_now()) {
+        eprintln!("Failed to record the push time: {}", err);
+    }
+}
+
+// Returns the exit code of the hook, push times are only recorded once the push goes through.
+fn finish_push(rejected: &[String], can_reject: bool, state: &EvaluationState) -> i32 {
+    // explain mode never enforces the outcome, post-receive only reports it
+    if state.explain() {
+        return 0;
+    }
+    if can_reject && !rejected.is_empty() {
+        return 1;
+    }
+    record_cooldowns(state);
+    0
 }
 
 fn main() {
+    if env::args().nth(1).is_some_and(|arg| arg == "--print-schema") {
+        let schema = serde_json::to_string_pretty(&Configuration::schema()).expect("the schema is valid JSON");
+        println!("{}", schema);
+        exit(0)
+    }
+
     let default_branch = match get_default_branch() {
         Some(branch) => branch,
         None => exit(0)
@@ -280,19 +441,22 @@ fn main() {
         Ok(Some(configuration)) => configuration,
         Ok(None) => exit(0),
         Err(err) => {
-            eprintln!("Failed to parse hook configuration: {}", err);
+            eprintln!("Failed to load hook configuration: {}", err);
             exit(0)
         }
     };
 
-    let config = match config {
-        Configuration::Version1(v1) => v1
-    };
+    let Configuration::Version1(config) = config;
+    let state = EvaluationState::new(config.max_total_duration, env_as::<String>("GIT_WEBBED_HOOK_EXPLAIN").is_some_and(|value| value == "1"));
+    if let Some(deadline) = state.deadline() {
+        set_deadline(deadline);
+    }
 
     let push_options = get_push_options();
     attempt_bypass(&push_options, &config.bypass);
 
     if let Some((hook, hook_type)) = config.select_hook() {
+        let can_reject = hook_type.can_reject();
 
         let changes = match get_changes(hook_type) {
             Some(changes) => changes,
@@ -301,33 +465,254 @@ fn main() {
             }
         };
 
-        let resolved_changes = resolve_changes(changes, default_branch.as_str());
+        let resolved_changes = resolve_changes(changes, default_branch.as_str(), config.diff_range.unwrap_or_default(), hook.max_scanned_commits);
+        let metadata = get_metadata();
+        let push_signature = get_push_signature();
+        let push = Push {
+            default_branch: default_branch.as_str(),
+            push_options: push_options.as_slice(),
+            changes: resolved_changes.as_slice(),
+            metadata: &metadata,
+            push_signature: push_signature.as_ref(),
+        };
 
-        for change in resolved_changes.iter() {
-            let ctx = RuleContext {
-                default_branch: default_branch.as_str(),
-                push_options: push_options.as_slice(),
-                change,
-                config: &config,
-            };
+        let rejected = evaluate_push(&config, hook, hook_type, &push, &state);
+        exit(finish_push(&rejected, can_reject, &state));
+    }
+}
 
-            match hook.rule.evaluate(&ctx, 0) {
-                Ok(RuleResult { action, messages }) => {
-                    match action {
-                        RuleAction::Accept => accept(messages),
-                        RuleAction::Continue => accept(messages),
-                        RuleAction::Reject => reject(messages),
-                    }
-                }
-                Err(err) => {
-                    let reject_on_err = hook.reject_on_error.unwrap_or(true);
-                    if reject_on_err {
-                        reject(vec![format!("change rejected, evaluation failed: {}", err)]);
-                    } else {
-                        accept(vec![format!("change accepted, but evaluation failed: {}", err)]);
-                    }
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cooldown::{PushTimes, PUSH_TIMES_FILE};
+    use crate::git::git_dir;
+    use crate::git::test_repo::TestRepo;
+
+    fn add_ref(name: &str, commit: &str) -> Change {
+        Change::AddRef {
+            name: name.to_string(),
+            commit: commit.to_string(),
+            git_data: GitData {
+                patch: Box::new(Box::new(None)),
+                log: Box::new(Box::new(vec![])),
+                file_status: Box::new(Box::new(vec![])),
+                diff_stat: Box::new(Box::new(None)),
+                log_limit: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_load_config_from_pushed_ref() {
+        let repo = TestRepo::new();
+        let configured = repo.commit(&[("hooks.yaml", "version: \"1\"\ntrace: true\n")], "Add hooks");
+        let broken = repo.commit(&[("hooks.yaml", "version: [\n")], "Break hooks");
+        repo.git(&["rm", "--quiet", "hooks.yaml"]);
+        let removed = repo.commit(&[], "Remove hooks");
+
+        let pushed = load_config_from_pushed_ref(&add_ref("refs/heads/feature", configured.as_str()), "main");
+        assert_eq!(pushed.and_then(|config| config.trace), Some(true));
+        assert!(load_config_from_pushed_ref(&add_ref("refs/heads/main", configured.as_str()), "main").is_none());
+        assert!(load_config_from_pushed_ref(&add_ref("refs/heads/feature", broken.as_str()), "main").is_none());
+        assert!(load_config_from_pushed_ref(&add_ref("refs/heads/feature", removed.as_str()), "main").is_none());
+    }
+
+    #[test]
+    fn test_pushed_ref_config_only_adds_checks() {
+        let repo = TestRepo::new();
+        let permissive = repo.commit(&[("hooks.yaml", indoc::indoc! {"
+            version: '1'
+            pre-receive:
+              reject-on-error: false
+              rule:
+                type: accept
+                messages: []
+        "})], "Accept everything");
+        let strict = repo.commit(&[("hooks.yaml", indoc::indoc! {"
+            version: '1'
+            pre-receive:
+              rule:
+                type: reject
+                messages: [feature branches are frozen]
+        "})], "Reject everything");
+        let failing = repo.commit(&[("hooks.yaml", indoc::indoc! {"
+            version: '1'
+            pre-receive:
+              reject-on-error: false
+              rule:
+                condition:
+                  type: signed-chain-from-anchor
+                  anchor_commit: does-not-exist
+        "})], "Fail evaluation");
+        let requesting = repo.commit(&[("hooks.yaml", indoc::indoc! {"
+            version: '1'
+            pre-receive:
+              rule:
+                type: select
+                first_of:
+                  - condition:
+                      type: not
+                      condition:
+                        type: branch-allowed-by-service
+                        url: http://127.0.0.1:1/allowlist
+                    rule:
+                      type: webhook
+                      url: http://127.0.0.1:1/webhook
+                      headers:
+                        Authorization: ${HOME}
+        "})], "Make requests");
+        let config: ConfigurationVersion1 = serde_yml::from_str(indoc::indoc! {"
+            config-source: pushed-ref
+            pre-receive:
+              rule:
+                condition:
+                  type: not
+                  condition:
+                    type: ref-matches
+                    pattern: ^refs/heads/wip/
+        "}).expect("config should parse");
+        let rejected = |ref_name: &str, commit: &str| !evaluate_pushed(&config, HookType::PreReceive, &[add_ref(ref_name, commit)], &EvaluationState::default()).0.is_empty();
+
+        // the pushed configuration can neither replace the default branch's rule nor its error handling
+        assert!(rejected("refs/heads/wip/feature", permissive.as_str()));
+        assert!(!rejected("refs/heads/feature", permissive.as_str()));
+        assert!(rejected("refs/heads/feature", strict.as_str()));
+        assert!(rejected("refs/heads/feature", failing.as_str()));
+
+        // the unreachable service would fail the evaluation, so not being rejected shows that nothing was requested
+        assert!(!rejected("refs/heads/feature", requesting.as_str()));
+    }
+
+    #[test]
+    fn test_config_discovery() {
+        let repo = TestRepo::new();
+        let json = repo.commit(&[("hooks.json", "{\"version\": \"1\", \"trace\": true}")], "Add JSON hooks");
+        let broken_toml = repo.commit(&[("hooks.toml", "version = ")], "Add broken TOML hooks");
+
+        match load_config_from_rev(json.as_str()) {
+            Ok(Some(Configuration::Version1(config))) => assert_eq!(config.trace, Some(true)),
+            other => panic!("expected the JSON configuration, got {:?}", other),
+        }
+        match load_config_from_rev(broken_toml.as_str()) {
+            Err(ConfigError::Parse { file, format: ConfigFormat::Toml, .. }) => assert_eq!(file, "hooks.toml"),
+            other => panic!("expected a parse error, got {:?}", other),
         }
     }
+
+    #[test]
+    fn test_zero_hashes() {
+        let sha1_zeros = "0".repeat(40);
+        let sha256_zeros = "0".repeat(64);
+        assert!(is_hash_all_zeros(&sha1_zeros, Some(40)));
+        assert!(!is_hash_all_zeros(&sha1_zeros, Some(64)));
+        assert!(is_hash_all_zeros(&sha256_zeros, Some(64)));
+        assert!(is_hash_all_zeros(&sha256_zeros, None));
+        assert!(!is_hash_all_zeros(&"0123456789abcdef".repeat(4), Some(64)));
+        assert!(!is_hash_all_zeros("", None));
+
+        let repo = TestRepo::new();
+        repo.git(&["config", "core.repositoryformatversion", "1"]);
+        repo.git(&["config", "extensions.objectformat", "sha256"]);
+        let commit = repo.commit(&[("README.md", "hello")], "Initial commit");
+        let line = |old_commit: &str, new_commit: &str| ChangeLine {
+            old_commit: old_commit.to_string(),
+            new_commit: new_commit.to_string(),
+            ref_name: "refs/heads/main".to_string(),
+        };
+
+        assert!(matches!(resolve_change(line(&sha256_zeros, &commit), "main", DiffRange::TwoDot, None), Some(Change::AddRef { .. })));
+        assert!(matches!(resolve_change(line(&commit, &sha256_zeros), "main", DiffRange::TwoDot, None), Some(Change::RemoveRef { .. })));
+    }
+
+    fn evaluate_pushed(config: &ConfigurationVersion1, hook_type: HookType, changes: &[Change], state: &EvaluationState) -> (Vec<String>, i32) {
+        let metadata = Metadata::None;
+        let push = Push {
+            default_branch: "main",
+            push_options: &[],
+            changes,
+            metadata: &metadata,
+            push_signature: None,
+        };
+        let hook = config.hook(hook_type).expect("hook should be configured");
+        let rejected = evaluate_push(config, hook, hook_type, &push, state);
+        let exit_code = finish_push(&rejected, hook_type.can_reject(), state);
+        (rejected, exit_code)
+    }
+
+    fn cooldown_config(hook: &str) -> ConfigurationVersion1 {
+        serde_yml::from_str(format!(indoc::indoc! {"
+            {}:
+              rule:
+                condition:
+                  type: and
+                  conditions:
+                    - type: ref-push-cooldown
+                      seconds: 60
+                    - type: is-default-branch
+        "}, hook).as_str()).expect("config should parse")
+    }
+
+    #[test]
+    fn test_push_cooldown_recorded_on_acceptance() {
+        let repo = TestRepo::new();
+        let commit = repo.commit(&[("README.md", "hello")], "Initial commit");
+        let config = cooldown_config("pre-receive");
+        let state_file = git_dir().expect("git directory should exist").join(PUSH_TIMES_FILE);
+        let elapsed = |ref_name: &str| PushTimes::lock(&state_file).expect("state should be lockable").elapsed(ref_name, unix_now(), 60);
+        let recorded = || std::fs::read_to_string(&state_file).expect("state should be readable");
+
+        // the rejected push went nowhere, so it doesn't start a cooldown
+        let (rejected, exit_code) = evaluate_pushed(&config, HookType::PreReceive, &[add_ref("refs/heads/feature", commit.as_str())], &EvaluationState::default());
+        assert_eq!(rejected, vec!["refs/heads/feature"]);
+        assert_eq!(exit_code, 1);
+        assert_eq!(recorded(), "");
+
+        // explain mode is a dry run
+        let (rejected, exit_code) = evaluate_pushed(&config, HookType::PreReceive, &[add_ref("refs/heads/main", commit.as_str())], &EvaluationState::new(None, true));
+        assert!(rejected.is_empty());
+        assert_eq!(exit_code, 0);
+        assert_eq!(recorded(), "");
+
+        let (rejected, exit_code) = evaluate_pushed(&config, HookType::PreReceive, &[add_ref("refs/heads/main", commit.as_str())], &EvaluationState::default());
+        assert!(rejected.is_empty());
+        assert_eq!(exit_code, 0);
+        assert!(!elapsed("refs/heads/main"));
+        assert!(elapsed("refs/heads/feature"));
+    }
+
+    #[test]
+    fn test_all_rejections_reported() {
+        let repo = TestRepo::new();
+        let commit = repo.commit(&[("README.md", "hello")], "Initial commit");
+        let config: ConfigurationVersion1 = serde_yml::from_str("pre-receive:\n  rule:\n    condition:\n      type: is-default-branch")
+            .expect("config should parse");
+
+        let changes = [
+            add_ref("refs/heads/feature-a", commit.as_str()),
+            add_ref("refs/heads/main", commit.as_str()),
+            add_ref("refs/heads/feature-b", commit.as_str()),
+        ];
+        let (rejected, exit_code) = evaluate_pushed(&config, HookType::PreReceive, &changes, &EvaluationState::default());
+        assert_eq!(rejected, vec!["refs/heads/feature-a", "refs/heads/feature-b"]);
+        assert_eq!(exit_code, 1);
+    }
+
+    #[test]
+    fn test_post_receive_never_rejects() {
+        let repo = TestRepo::new();
+        let commit = repo.commit(&[("README.md", "hello")], "Initial commit");
+        assert!(!HookType::PostReceive.can_reject());
+
+        let config: ConfigurationVersion1 = serde_yml::from_str("post-receive:\n  rule:\n    type: reject\n    messages: [nope]")
+            .expect("config should parse");
+        let (rejected, exit_code) = evaluate_pushed(&config, HookType::PostReceive, &[add_ref("refs/heads/main", commit.as_str())], &EvaluationState::default());
+        assert_eq!(rejected, vec!["refs/heads/main"]);
+        assert_eq!(exit_code, 0);
+
+        // the push already happened, so its time counts towards the cooldown
+        let state_file = git_dir().expect("git directory should exist").join(PUSH_TIMES_FILE);
+        let (_, exit_code) = evaluate_pushed(&cooldown_config("post-receive"), HookType::PostReceive, &[add_ref("refs/heads/feature", commit.as_str())], &EvaluationState::default());
+        assert_eq!(exit_code, 0);
+        assert!(!PushTimes::lock(&state_file).expect("state should be lockable").elapsed("refs/heads/feature", unix_now(), 60));
+    }
 }